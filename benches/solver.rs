@@ -0,0 +1,220 @@
+//! Benchmarks for building the armature graph and solving it, on synthetic rigs.
+use bevy::{ecs::system::System, prelude::*, transform::transform_propagate_system};
+use bevy_ik::{
+    cache_ik_data, compute_joint_positions, create_armature_tree, index_bones,
+    resolve_goal_positions, Bone, BoneBundle, IkData, IkGoal, IkGoalBundle,
+    InverseKinematicsPlugin,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// every goal is moved away from its target bone by this offset, so the solver has to do some work
+const GOAL_OFFSET: Vec3 = Vec3::new(0.05, -0.1, 0.08);
+
+/// A synthetic rig. All children of a bone start at the tail of that bone.
+#[derive(Default)]
+struct Rig {
+    /// name, parent index and tail of each bone - parents come before their children
+    bones: Vec<(String, Option<usize>, Vec3)>,
+    /// target bone index and chain length of each goal
+    goals: Vec<(usize, u32)>,
+}
+
+impl Rig {
+    fn add(&mut self, name: &str, parent: Option<usize>, tail: Vec3) -> usize {
+        self.bones.push((name.to_string(), parent, tail));
+        self.bones.len() - 1
+    }
+
+    /// adds a chain of bones below `parent` and returns the index of the last one
+    fn add_chain(&mut self, name: &str, parent: usize, tails: &[Vec3]) -> usize {
+        tails.iter().enumerate().fold(parent, |parent, (i, tail)| {
+            self.add(&format!("{}{}", name, i), Some(parent), *tail)
+        })
+    }
+}
+
+/// a single chain of 100 bones, with a goal at its end
+fn long_chain() -> Rig {
+    let mut rig = Rig::default();
+    let root = rig.add("root", None, Vec3::Y * 0.1);
+    let leaf = rig.add_chain("bone", root, &[Vec3::Y * 0.1; 99]);
+    rig.goals.push((leaf, 99));
+    rig
+}
+
+/// spine, head, arms and legs, with goals on the head, the hands and the feet
+fn humanoid() -> Rig {
+    let mut rig = Rig::default();
+    let hips = rig.add("hips", None, Vec3::Y * 0.1);
+    let spine = rig.add_chain("spine", hips, &[Vec3::Y * 0.15; 3]);
+    let head = rig.add_chain("head", spine, &[Vec3::Y * 0.1, Vec3::Y * 0.2]);
+    rig.goals.push((head, 4));
+    for (side, x) in [("l", 1.), ("r", -1.)] {
+        let hand = rig.add_chain(
+            &format!("arm_{}", side),
+            spine,
+            &[
+                Vec3::new(0.15 * x, 0., 0.),
+                Vec3::new(0.3 * x, -0.05, 0.),
+                Vec3::new(0.25 * x, 0., 0.),
+                Vec3::new(0.1 * x, 0., 0.),
+            ],
+        );
+        rig.goals.push((hand, 2));
+        let toes = rig.add_chain(
+            &format!("leg_{}", side),
+            hips,
+            &[
+                Vec3::new(0.1 * x, -0.45, 0.),
+                Vec3::new(0., -0.45, 0.),
+                Vec3::new(0., -0.05, 0.15),
+                Vec3::new(0., 0., 0.05),
+            ],
+        );
+        rig.goals.push((toes - 1, 2));
+    }
+    rig
+}
+
+/// a wrist with five fingers, with a goal on each finger tip
+fn hand() -> Rig {
+    let mut rig = Rig::default();
+    let wrist = rig.add("wrist", None, Vec3::Y * 0.08);
+    for (finger, x) in [-0.04, -0.02, 0., 0.02, 0.04].into_iter().enumerate() {
+        let tip = rig.add_chain(
+            &format!("finger{}_", finger),
+            wrist,
+            &[
+                Vec3::new(x, 0.08, 0.),
+                Vec3::Y * 0.04,
+                Vec3::Y * 0.03,
+                Vec3::Y * 0.02,
+                Vec3::Y * 0.01,
+            ],
+        );
+        rig.goals.push((tip, 3));
+    }
+    rig
+}
+
+/// Spawns `count` copies of the rig side by side, and runs the solver systems up to the point where the joint
+/// positions are computed.
+fn setup(rig: &Rig, count: usize) -> App {
+    let mut app = App::new();
+    app.add_plugin(InverseKinematicsPlugin::default());
+    let world = &mut app.world;
+
+    for copy in 0..count {
+        let origin = Vec3::new((copy % 10) as f32, 0., (copy / 10) as f32) * 2.;
+        let mut bone_ids = Vec::<Entity>::new();
+        let mut positions = Vec::<Vec3>::new();
+        for (name, parent, _) in rig.bones.iter() {
+            let (translation, position) = match parent {
+                Some(parent) => (
+                    rig.bones[*parent].2,
+                    positions[*parent] + rig.bones[*parent].2,
+                ),
+                None => (origin, origin),
+            };
+            let bone_id = world
+                .spawn(BoneBundle {
+                    bone: Bone { name: name.clone() },
+                    transform: Transform::from_translation(translation),
+                    ..default()
+                })
+                .id();
+            if let Some(parent) = parent {
+                world
+                    .entity_mut(bone_ids[*parent])
+                    .push_children(&[bone_id]);
+            }
+            bone_ids.push(bone_id);
+            positions.push(position);
+        }
+        for (bone, chain_length) in rig.goals.iter() {
+            let position = positions[*bone] + GOAL_OFFSET;
+            world.spawn(IkGoalBundle {
+                goal: IkGoal {
+                    target_bone: bone_ids[*bone],
+                    chain_length: *chain_length,
+                },
+                transform: Transform::from_translation(position),
+                global_transform: GlobalTransform::from_translation(position),
+            });
+        }
+    }
+
+    run_once(world, transform_propagate_system);
+    run_once(world, index_bones);
+    run_once(world, create_armature_tree);
+    run_once(world, cache_ik_data);
+    run_once(world, resolve_goal_positions);
+    app
+}
+
+fn run_once<Param>(world: &mut World, system: impl IntoSystem<(), (), Param>) {
+    let mut system = IntoSystem::into_system(system);
+    system.initialize(world);
+    system.run((), world);
+}
+
+fn rigs() -> Vec<(&'static str, App)> {
+    vec![
+        ("long_chain", setup(&long_chain(), 1)),
+        ("humanoid", setup(&humanoid(), 1)),
+        ("hand", setup(&hand(), 1)),
+        ("crowd_100", setup(&humanoid(), 100)),
+    ]
+}
+
+fn bench_create_armature_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_armature_tree");
+    for (name, mut app) in rigs() {
+        let world = &mut app.world;
+        let mut system = IntoSystem::into_system(create_armature_tree);
+        system.initialize(world);
+        group.bench_function(name, |b| b.iter(|| system.run((), world)));
+    }
+    group.finish();
+}
+
+fn bench_cache_ik_data(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache_ik_data");
+    for (name, mut app) in rigs() {
+        let world = &mut app.world;
+        let mut system = IntoSystem::into_system(cache_ik_data);
+        system.initialize(world);
+        group.bench_function(name, |b| b.iter(|| system.run((), world)));
+    }
+    group.finish();
+}
+
+fn bench_compute_joint_positions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_joint_positions");
+    for (name, mut app) in rigs() {
+        let world = &mut app.world;
+        let mut system = IntoSystem::into_system(compute_joint_positions);
+        system.initialize(world);
+        // every iteration starts from the same unsolved pose
+        let unsolved = world.resource::<IkData>().clone();
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || unsolved.clone(),
+                |data| {
+                    *world.resource_mut::<IkData>() = data;
+                    system.run((), world);
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_create_armature_tree,
+    bench_cache_ik_data,
+    bench_compute_joint_positions
+);
+criterion_main!(benches);
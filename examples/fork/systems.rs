@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use bevy_ik::{Bone, BoneBundle, IkGoal, IkGoalBundle};
+use bevy_ik::{Bone, BoneBundle, BoneRef, IkGoal, IkGoalTarget, IkGoalTargetBundle};
 
 use crate::{
     components::{BoneVizHandles, GoalVizHandles},
@@ -151,31 +151,17 @@ pub fn setup_fork_armature(mut commands: Commands) {
         });
 }
 
-pub fn setup_goals(
-    mut commands: Commands,
-    bones: Query<(Entity, &Bone)>,
-    assets: Res<GoalVizHandles>,
-) {
+pub fn setup_goals(mut commands: Commands, assets: Res<GoalVizHandles>) {
     for (target_bone_name, chain_length) in TARGETS.iter() {
-        let target_id = bones
-            .iter()
-            .filter_map(|(id, bone)| {
-                if bone.name == *target_bone_name {
-                    Some(id)
-                } else {
-                    None
-                }
-            })
-            .next()
-            .expect("No valid bone found");
-
         commands
-            .spawn(IkGoalBundle {
+            .spawn(IkGoalTargetBundle {
                 transform: Transform::from_xyz(GOAL_INIT[0], GOAL_INIT[1], GOAL_INIT[2]),
                 global_transform: GlobalTransform::default(),
-                goal: IkGoal {
-                    target_bone: target_id,
+                // the goal is resolved once the bone exists
+                target: IkGoalTarget {
+                    bone: BoneRef::Name(target_bone_name.to_string()),
                     chain_length: *chain_length,
+                    armature: None,
                 },
             })
             .with_children(|parent| {
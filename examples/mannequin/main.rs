@@ -2,7 +2,7 @@ mod components;
 mod systems;
 
 use bevy::prelude::*;
-use bevy_ik::InverseKinematicsPlugin;
+use bevy_ik::{InverseKinematicsPlugin, SkinnedMeshBonesPlugin};
 use components::MannequinInstance;
 use systems::*;
 
@@ -23,6 +23,7 @@ fn main() {
         .init_resource::<MannequinInstance>()
         .add_plugins(DefaultPlugins)
         .add_plugin(InverseKinematicsPlugin::default())
+        .add_plugin(SkinnedMeshBonesPlugin::default())
         .add_state(AppState::Loading)
         .add_system_set(
             SystemSet::on_enter(AppState::Loading)
@@ -30,7 +31,7 @@ fn main() {
                 .with_system(setup_camera)
                 .with_system(setup_goal_assets),
         )
-        .add_system_set(SystemSet::on_update(AppState::Loading).with_system(tag_mannequin))
+        .add_system_set(SystemSet::on_update(AppState::Loading).with_system(wait_for_mannequin))
         .add_system_set(SystemSet::on_enter(AppState::Running).with_system(setup_goals))
         .add_system_set(SystemSet::on_update(AppState::Running).with_system(rotate_goal))
         .run();
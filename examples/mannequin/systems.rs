@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use bevy_ik::{Bone, IkGoal, IkGoalBundle};
+use bevy_ik::{Bone, BoneRef, IkGoal, IkGoalTarget, IkGoalTargetBundle};
 
 use crate::{
     components::{GoalVizHandles, MannequinInstance},
@@ -60,56 +60,33 @@ pub fn setup_mannequin(
     scene_instance.0 = Some(instance_id);
 }
 
-pub fn tag_mannequin(
-    names: Query<&Name>,
-    mut commands: Commands,
+pub fn wait_for_mannequin(
+    bones: Query<(), With<Bone>>,
     scene_spawner: Res<SceneSpawner>,
     scene_instance: Res<MannequinInstance>,
     mut app_state: ResMut<State<AppState>>,
 ) {
     if let Some(instance_id) = scene_instance.0 {
-        if scene_spawner.instance_is_ready(instance_id) {
-            for entity in scene_spawner.iter_instance_entities(instance_id) {
-                if let Ok(name) = names.get(entity) {
-                    if name.contains("bone") {
-                        commands.entity(entity).insert(Bone {
-                            name: name.to_string(),
-                        });
-                    }
-                }
-            }
+        // the SkinnedMeshBonesPlugin tags the skin joints once the scene instance is ready
+        if scene_spawner.instance_is_ready(instance_id) && !bones.is_empty() {
             app_state.set(AppState::Running).unwrap();
         }
     }
 }
 
-pub fn setup_goals(
-    mut commands: Commands,
-    bones: Query<(Entity, &Bone)>,
-    assets: Res<GoalVizHandles>,
-) {
-    let targets = vec![("bone_hand.L", 1)];
+pub fn setup_goals(mut commands: Commands, assets: Res<GoalVizHandles>) {
+    let targets = [("bone_hand.L", 1)];
 
     for (target_bone_name, chain_length) in targets.iter() {
-        let target_id = bones
-            .iter()
-            .filter_map(|(id, bone)| {
-                if bone.name == *target_bone_name {
-                    Some(id)
-                } else {
-                    None
-                }
-            })
-            .next()
-            .expect("No valid bone found");
-
         commands
-            .spawn(IkGoalBundle {
+            .spawn(IkGoalTargetBundle {
                 transform: Transform::from_xyz(0.0, 6.0, 0.0),
                 global_transform: GlobalTransform::default(),
-                goal: IkGoal {
-                    target_bone: target_id,
+                // the goal is resolved once the bone exists
+                target: IkGoalTarget {
+                    bone: BoneRef::Name(target_bone_name.to_string()),
                     chain_length: *chain_length,
+                    armature: None,
                 },
             })
             .with_children(|parent| {
@@ -0,0 +1,70 @@
+use crate::components::{Bone, IkData, IkSmoothing, RestPose};
+use bevy::{
+    ecs::system::{Command, SystemState},
+    prelude::*,
+};
+
+/// Captures the current pose of the armature with the given root bone as its [`RestPose`],
+/// replacing any previously captured one.
+pub struct CaptureRestPose {
+    pub armature: Entity,
+}
+
+impl Command for CaptureRestPose {
+    fn write(self, world: &mut World) {
+        let mut state =
+            SystemState::<Query<(&Transform, Option<&Children>), With<Bone>>>::new(world);
+        let bones = state.get(world);
+        let rest_pose = RestPose::capture(self.armature, &bones);
+        world.entity_mut(self.armature).insert(rest_pose);
+    }
+}
+
+/// Resets all bones of the armature with the given root bone to its [`RestPose`].
+/// Also discards the warm start and smoothing state of the armature, so the next solve starts from the rest pose.
+pub struct ResetToRestPose {
+    pub armature: Entity,
+}
+
+impl Command for ResetToRestPose {
+    fn write(self, world: &mut World) {
+        let rest_pose = match world.get::<RestPose>(self.armature) {
+            Some(rest_pose) => rest_pose.clone(),
+            None => {
+                warn!("Armature {:?} has no rest pose", self.armature);
+                return;
+            }
+        };
+
+        for (bone_id, rest_tf) in rest_pose.transforms.iter() {
+            if let Some(mut bone_tf) = world.get_mut::<Transform>(*bone_id) {
+                *bone_tf = *rest_tf;
+            }
+        }
+
+        if let Some(mut smoothing) = world.get_mut::<IkSmoothing>(self.armature) {
+            smoothing.last_rotations.clear();
+        }
+
+        let mut data = world.resource_mut::<IkData>();
+        data.last_positions
+            .retain(|_, (root_id, _)| *root_id != self.armature);
+    }
+}
+
+pub trait RestPoseCommands {
+    /// See [`CaptureRestPose`].
+    fn capture_rest_pose(&mut self, armature: Entity);
+    /// See [`ResetToRestPose`].
+    fn reset_to_rest_pose(&mut self, armature: Entity);
+}
+
+impl<'w, 's> RestPoseCommands for Commands<'w, 's> {
+    fn capture_rest_pose(&mut self, armature: Entity) {
+        self.add(CaptureRestPose { armature });
+    }
+
+    fn reset_to_rest_pose(&mut self, armature: Entity) {
+        self.add(ResetToRestPose { armature });
+    }
+}
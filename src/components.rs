@@ -1,13 +1,26 @@
+use crate::{
+    solver::{Chains, Skeleton},
+    DEFAULT_GOAL_TOLERANCE, DEFAULT_MAX_ITERATIONS,
+};
 use bevy::{
+    ecs::{
+        entity::{EntityMap, MapEntities, MapEntitiesError},
+        reflect::ReflectMapEntities,
+    },
     prelude::*,
     utils::{HashMap, HashSet},
 };
 
+/// Placeholder for the entity fields of components created by reflection, e.g. while a scene is loaded.
+/// The actual entities are set by [`MapEntities`] or when the component is inserted.
+pub(crate) const PLACEHOLDER_ENTITY: Entity = Entity::from_raw(u32::MAX);
+
 // Resources
 
 /// The [`ArmatureGraph`] contains information about the [`Bone`] tree.
 /// It is updated once per frame. Treat this resource as read-only.
-#[derive(Default, Resource)]
+#[derive(Default, Resource, Reflect)]
+#[reflect(Resource)]
 pub struct ArmatureGraph {
     /// joint ids and their outgoing bones
     pub out_bones: HashMap<u32, HashSet<Entity>>,
@@ -24,37 +37,401 @@ pub struct ArmatureGraph {
 }
 
 /// [`IkData`] contains intermediate results of the FABRIK algorithm. Treat this resource as read-only.
-#[derive(Default, Debug, Resource)]
+#[derive(Default, Debug, Clone, Resource, Reflect)]
+#[reflect(Resource)]
 pub struct IkData {
     /// armature joints and their global positions. A joint is between two bones.
     pub joint_positions: HashMap<u32, Vec3>,
     /// Length of each bone (distance between joints)
     pub bone_lengths: HashMap<Entity, f32>,
-    /// for each joint, which children joints do we need info from? (some joints might not have IK goals)
-    pub required_positions: HashMap<u32, HashSet<u32>>,
+    /// the joint tree of all armatures, as seen by the solver
+    pub skeleton: Skeleton,
+    /// the joints which are solved for the current goals
+    pub chains: Chains,
     /// hashmap of joint ids to goal ids
     pub joints_to_goals: HashMap<u32, Entity>,
-    /// FABRIK roots - joints defined by not having a parent, or by chain length, or if fixed
-    pub roots: HashSet<u32>,
+    /// resolved global position of each goal, see [`IkGoalAnchor`]
+    pub goal_positions: HashMap<Entity, Vec3>,
+    /// for each bone in a chain, its armature root bone and the solved position of its base joint
+    /// relative to the position of that root bone, in the space of its parent. Used to warm start the next frame.
+    pub last_positions: HashMap<Entity, (Entity, Vec3)>,
+}
+
+/// The [`BoneIndex`] maps bone names and paths to bone entities, scoped per armature.
+/// It is updated once per frame. Treat this resource as read-only.
+#[derive(Default, Debug, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct BoneIndex {
+    /// root bones of each armature and the bones of that armature
+    pub armatures: HashMap<Entity, ArmatureBones>,
+}
+
+impl BoneIndex {
+    /// Looks up a bone in the armature with the given root bone.
+    pub fn get(&self, armature: Entity, bone: &BoneRef) -> Option<Entity> {
+        self.armatures.get(&armature)?.get(bone)
+    }
+
+    /// Looks up a bone in any armature. If multiple armatures contain a matching bone, any of them is returned.
+    pub fn find(&self, bone: &BoneRef) -> Option<Entity> {
+        self.armatures.values().find_map(|bones| bones.get(bone))
+    }
+}
+
+#[derive(Default, Debug, Reflect, FromReflect)]
+pub struct ArmatureBones {
+    /// all bones of the armature
+    pub bones: Vec<Entity>,
+    /// bone names and their bone ids. Names of multiple bones are left out, so they can't resolve to the wrong bone.
+    pub by_name: HashMap<String, Entity>,
+    /// bone paths (bone names from the root bone to the bone, separated by `/`) and their bone ids.
+    /// Like names, paths of multiple bones are left out.
+    pub by_path: HashMap<String, Entity>,
 }
 
-#[derive(Default, Debug, Resource)]
+impl ArmatureBones {
+    pub fn get(&self, bone: &BoneRef) -> Option<Entity> {
+        match bone {
+            BoneRef::Name(name) => self.by_name.get(name).copied(),
+            BoneRef::Path(path) => self.by_path.get(path).copied(),
+        }
+    }
+}
+
+#[derive(Debug, Resource, Reflect)]
+#[reflect(Resource)]
 pub struct IkSettings {
     pub goal_tolerance: f32,
     pub max_iterations: u32,
+    /// start solving from the previous frame's solution instead of the current bone positions
+    pub warm_start: bool,
+    /// which twist solved bones keep when they are rotated towards their new joint positions
+    pub twist_source: TwistSource,
+    /// solve whole armatures instead of single chains: chain lengths are ignored and the armature roots may move,
+    /// while the center of mass is kept above the [`Planted`] goals
+    pub full_body: bool,
 }
 
-#[derive(Component, Copy, Clone)]
+impl IkSettings {
+    /// The chain length used for a goal with the given chain length.
+    pub fn chain_length(&self, chain_length: u32) -> u32 {
+        match self.full_body {
+            true => u32::MAX,
+            false => chain_length,
+        }
+    }
+}
+
+impl Default for IkSettings {
+    fn default() -> Self {
+        Self {
+            goal_tolerance: DEFAULT_GOAL_TOLERANCE,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            warm_start: false,
+            twist_source: TwistSource::default(),
+            full_body: false,
+        }
+    }
+}
+
+/// Solved bones are only swung towards their new joint positions. Their twist around the bone axis
+/// is taken from this source, so it can't accumulate over time.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Reflect, FromReflect)]
+pub enum TwistSource {
+    /// keep the twist of the [`RestPose`]
+    #[default]
+    Rest,
+    /// keep the twist of the current (e.g. animated) bone rotation
+    Animated,
+}
+
+#[derive(Component, Copy, Clone, Reflect)]
+#[reflect(Component, MapEntities)]
 pub struct IkGoal {
     pub target_bone: Entity,
     pub chain_length: u32,
 }
 
-#[derive(Component, Default)]
+impl FromWorld for IkGoal {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            target_bone: PLACEHOLDER_ENTITY,
+            chain_length: 0,
+        }
+    }
+}
+
+impl MapEntities for IkGoal {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.target_bone = entity_map.get(self.target_bone)?;
+        Ok(())
+    }
+}
+
+/// Makes an [`IkGoal`] follow another entity instead of its own [`GlobalTransform`],
+/// e.g. a hand goal that tracks the grip socket of a weapon.
+/// The goal position is the anchor's [`GlobalTransform`] combined with the local `offset`.
+#[derive(Component, Copy, Clone, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct IkGoalAnchor {
+    pub entity: Entity,
+    pub offset: Transform,
+}
+
+impl FromWorld for IkGoalAnchor {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            entity: PLACEHOLDER_ENTITY,
+            offset: Transform::IDENTITY,
+        }
+    }
+}
+
+impl MapEntities for IkGoalAnchor {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        // the anchor might live outside of the scene, in which case it is not mapped
+        if let Ok(mapped_entity) = entity_map.get(self.entity) {
+            self.entity = mapped_entity;
+        }
+        Ok(())
+    }
+}
+
+/// Links two [`IkGoal`]s, e.g. the hands holding a rifle, so they keep their relative position. If one of the goals
+/// can't be reached, both goals are moved by the same offset and the error is shared between both chains.
+/// Insert it on one of the goals.
+#[derive(Component, Copy, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct IkGoalLink {
+    /// the other goal
+    pub other: Entity,
+    /// how far the target bone of this goal ended up from the goal, maintained by the solver. Both linked goals
+    /// have the same error.
+    pub error: Vec3,
+}
+
+impl FromWorld for IkGoalLink {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            other: PLACEHOLDER_ENTITY,
+            error: Vec3::ZERO,
+        }
+    }
+}
+
+impl MapEntities for IkGoalLink {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.other = entity_map.get(self.other)?;
+        Ok(())
+    }
+}
+
+/// The rest pose of an armature: local transforms of all bones and the bone lengths.
+/// Captured once per armature and inserted on its root bone. The solver always uses the rest lengths,
+/// so stretched animation frames can't change the rig permanently.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct RestPose {
+    /// local transforms of the bones
+    pub transforms: HashMap<Entity, Transform>,
+    /// length of each bone (distance between joints) in the space of its parent bone, so it doesn't depend on
+    /// the scale of the hierarchy above
+    pub bone_lengths: HashMap<Entity, f32>,
+}
+
+impl RestPose {
+    /// Records the current local transforms of the armature with the given root bone.
+    pub fn capture(
+        root_id: Entity,
+        bones: &Query<(&Transform, Option<&Children>), With<Bone>>,
+    ) -> Self {
+        let mut rest_pose = RestPose::default();
+
+        // walk down the bone tree
+        let mut todo_stack = vec![root_id];
+        while let Some(bone_id) = todo_stack.pop() {
+            let (bone_tf, children) = match bones.get(bone_id) {
+                Ok(bone) => bone,
+                Err(_) => continue,
+            };
+            rest_pose.transforms.insert(bone_id, *bone_tf);
+
+            for &child_id in children.into_iter().flatten() {
+                if let Ok((child_tf, _)) = bones.get(child_id) {
+                    // all child bones share the same pole joint, the first one determines the bone length
+                    rest_pose
+                        .bone_lengths
+                        .entry(bone_id)
+                        .or_insert_with(|| (bone_tf.scale * child_tf.translation).length());
+                    todo_stack.push(child_id);
+                }
+            }
+        }
+
+        rest_pose
+    }
+}
+
+impl MapEntities for RestPose {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        // the bones belong to the armature of the rest pose, so they are part of the same scene
+        self.transforms = self
+            .transforms
+            .drain()
+            .map(|(bone_id, bone_tf)| Ok((entity_map.get(bone_id)?, bone_tf)))
+            .collect::<Result<_, _>>()?;
+        self.bone_lengths = self
+            .bone_lengths
+            .drain()
+            .map(|(bone_id, length)| Ok((entity_map.get(bone_id)?, length)))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+}
+
+/// Makes a helper bone (e.g. a forearm twist bone) follow a part of the twist of another bone (e.g. the hand),
+/// so skinned meshes don't candy-wrap. The twist of the source bone is measured relative to its [`RestPose`],
+/// and added to the current (e.g. animated) rotation of the helper bone.
+#[derive(Component, Copy, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct TwistHelper {
+    /// the bone to take the twist from
+    pub source: Entity,
+    /// the twist axis in the local space of both bones, usually the bone direction
+    pub axis: Vec3,
+    /// fraction of the source twist applied to the helper bone
+    pub weight: f32,
+    /// the rotation of the helper bone before and after the last twist, maintained by the solver
+    pub last_rotation: Option<(Quat, Quat)>,
+}
+
+impl FromWorld for TwistHelper {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            source: PLACEHOLDER_ENTITY,
+            axis: Vec3::Y,
+            weight: 0.5,
+            last_rotation: None,
+        }
+    }
+}
+
+impl MapEntities for TwistHelper {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.source = entity_map.get(self.source)?;
+        Ok(())
+    }
+}
+
+/// Smooths the rotations of the solved bones of an armature over time, so they don't snap to each new solution.
+/// Insert it on the root bone of the armature.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct IkSmoothing {
+    /// time in seconds until a bone has rotated half of the way to its solved rotation
+    pub half_life: f32,
+    /// maximum angular speed of a bone in radians per second
+    pub max_angular_speed: Option<f32>,
+    /// bone rotations of the previous frame, maintained by the solver
+    pub last_rotations: HashMap<Entity, Quat>,
+}
+
+impl Default for IkSmoothing {
+    fn default() -> Self {
+        Self {
+            half_life: 0.05,
+            max_angular_speed: None,
+            last_rotations: HashMap::new(),
+        }
+    }
+}
+
+impl MapEntities for IkSmoothing {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        // bones which are not part of the scene start without smoothing
+        self.last_rotations = self
+            .last_rotations
+            .drain()
+            .filter_map(|(bone_id, rotation)| Some((entity_map.get(bone_id).ok()?, rotation)))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Refers to a [`Bone`] by its name, or by its path: the names of all bones from the root bone
+/// of the armature to the bone, separated by `/`, e.g. `armature/spine/arm.L`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Reflect, FromReflect)]
+pub enum BoneRef {
+    Name(String),
+    Path(String),
+}
+
+impl Default for BoneRef {
+    fn default() -> Self {
+        Self::Name(String::new())
+    }
+}
+
+/// Declares an [`IkGoal`] by bone name instead of by entity. The goal is inserted as soon as the bone exists,
+/// e.g. once the scene containing the armature has been spawned. If the bone is despawned, e.g. when the scene
+/// is respawned, the goal is removed until a matching bone exists again.
+#[derive(Component, Clone, Default, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct IkGoalTarget {
+    pub bone: BoneRef,
+    pub chain_length: u32,
+    /// only look for the bone in armatures below this entity, e.g. the root bone or the scene root
+    pub armature: Option<Entity>,
+}
+
+impl MapEntities for IkGoalTarget {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        if let Some(armature) = self.armature {
+            self.armature = Some(entity_map.get(armature)?);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
 pub struct Bone {
     pub name: String,
 }
 
+/// The mass of a [`Bone`], used to compute the center of mass in full body mode, see [`IkSettings::full_body`].
+/// Bones without a mass weigh as much as they are long.
+#[derive(Component, Copy, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct BoneMass(pub f32);
+
+/// How much a [`Bone`] resists being rotated by the solver, from 0 (rotates freely) to 1 (keeps its direction).
+/// The other bones of the chain make up for stiff bones, e.g. a stiff lower spine below a flexible upper spine.
+#[derive(Component, Copy, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct BoneStiffness(pub f32);
+
+/// Marks an [`IkGoal`] as planted, e.g. a foot on the ground. In full body mode, the center of mass of the
+/// armature is kept above the polygon spanned by its planted goals.
+#[derive(Component, Copy, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Planted;
+
+/// Lets the root bone of an armature move towards the goals, by at most `max_offset`. The solver only moves the root
+/// if a goal's chain reaches it. The offset is reported for a character controller to consume as root motion:
+/// at the start of the next frame the root bone is moved back, so the controller can move the character
+/// by `offset` during [`CoreStage::Update`] instead.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct RootMotion {
+    /// maximum distance the root bone may move from its position before solving
+    pub max_offset: f32,
+    /// the global offset applied to the root bone by the last solve, maintained by the solver
+    pub offset: Vec3,
+    /// the local translation of the root bone before and after the last offset, maintained by the solver
+    pub last_translation: Option<(Vec3, Vec3)>,
+}
+
 // Bundles
 #[derive(Bundle, Default)]
 pub struct BoneBundle {
@@ -69,3 +446,10 @@ pub struct IkGoalBundle {
     pub transform: Transform,
     pub global_transform: GlobalTransform,
 }
+
+#[derive(Bundle)]
+pub struct IkGoalTargetBundle {
+    pub target: IkGoalTarget,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
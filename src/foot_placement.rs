@@ -0,0 +1,275 @@
+use crate::{
+    components::{Bone, IkGoal},
+    ground::Ground,
+    IkSystem,
+};
+use bevy::{
+    math::Affine3A,
+    prelude::*,
+    transform::{transform_propagate_system, TransformSystem},
+    utils::HashMap,
+};
+
+/// Places [`IkGoal`]s with a [`FootPlacement`] on the ground below their foot bones and lowers [`Pelvis`] bones,
+/// so all feet can reach the ground. The ground is queried from the [`Ground`] resource, which defaults
+/// to a flat ground at height 0.
+pub struct FootPlacementPlugin;
+
+impl Plugin for FootPlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Ground>()
+            .register_type::<FootPlacement>()
+            .register_type::<Pelvis>()
+            .register_type::<(Quat, Quat)>()
+            .register_type::<Option<(Quat, Quat)>>()
+            // the goals and the pelvis are moved before solving, so their transforms have to be propagated again
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .label(IkSystem::FootPlacement)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(IkSystem::Solve)
+                    .with_system(place_feet)
+                    .with_system(transform_propagate_system.after(place_feet)),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                align_feet
+                    .after(IkSystem::Solve)
+                    .before(IkSystem::Propagate),
+            );
+    }
+}
+
+/// Keeps the target bone of an [`IkGoal`] on the ground. Each frame, the goal is moved onto the ground
+/// below the foot bone. The goal entity must not have a parent.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component)]
+pub struct FootPlacement {
+    /// height of the foot bone above the ground
+    pub foot_height: f32,
+    /// rotate the foot bone, so its up axis follows the ground normal
+    pub align_to_normal: bool,
+    /// the up axis of the foot bone in its local space
+    pub up: Vec3,
+    /// the ground normal below the foot, maintained by the plugin
+    pub normal: Vec3,
+    /// the local rotation of the foot bone before and after the last alignment, maintained by the plugin
+    pub last_alignment: Option<(Quat, Quat)>,
+}
+
+impl Default for FootPlacement {
+    fn default() -> Self {
+        Self {
+            foot_height: 0.,
+            align_to_normal: true,
+            up: Vec3::Y,
+            normal: Vec3::Y,
+            last_alignment: None,
+        }
+    }
+}
+
+/// Lowers a bone (usually the pelvis or the hips), so the feet below it can reach their [`FootPlacement`] goals.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component)]
+pub struct Pelvis {
+    /// fraction of the full leg length the legs may be stretched to
+    pub max_extension: f32,
+    /// the global vertical offset applied to the bone, maintained by the plugin
+    pub offset: f32,
+    /// the local translation of the bone before and after the last offset, maintained by the plugin
+    pub last_translation: Option<(Vec3, Vec3)>,
+}
+
+impl Default for Pelvis {
+    fn default() -> Self {
+        Self {
+            max_extension: 0.98,
+            offset: 0.,
+            last_translation: None,
+        }
+    }
+}
+
+pub fn place_feet(
+    mut goals: Query<(
+        &IkGoal,
+        &mut FootPlacement,
+        &mut Transform,
+        &mut GlobalTransform,
+    )>,
+    mut pelvises: Query<(Entity, &mut Pelvis, &mut Transform), Without<IkGoal>>,
+    global_tfs: Query<&GlobalTransform, Without<IkGoal>>,
+    parents: Query<&Parent>,
+    ground: Res<Ground>,
+) {
+    // undo the offsets of the last frame, unless somebody else (e.g. an animation) has moved the pelvis since.
+    // The current global transforms still contain the undone offsets, in the current space of the parents,
+    // they are removed from the hips so the new offsets don't depend on the old ones.
+    let mut contained_offsets = HashMap::<Entity, Vec3>::new();
+    for (pelvis_id, pelvis, mut pelvis_tf) in pelvises.iter_mut() {
+        if let Some((original, applied)) = pelvis.last_translation {
+            if applied == pelvis_tf.translation {
+                pelvis_tf.translation = original;
+                let par_affine = parents
+                    .get(pelvis_id)
+                    .and_then(|parent| global_tfs.get(parent.get()))
+                    .map_or(Affine3A::IDENTITY, |par_gt| par_gt.affine());
+                contained_offsets
+                    .insert(pelvis_id, par_affine.transform_vector3(applied - original));
+            }
+        }
+    }
+
+    // pelvis bones and the vertical offset needed by the feet below them
+    let mut pelvis_offsets = Vec::<(Entity, f32)>::new();
+
+    for (goal, mut foot, mut goal_tf, mut goal_gt) in goals.iter_mut() {
+        let foot_pos = match global_tfs.get(goal.target_bone) {
+            Ok(foot_gt) => foot_gt.translation(),
+            Err(_) => continue,
+        };
+
+        // put the goal onto the ground below the foot, if there is any
+        let ground_pos = Vec2::new(foot_pos.x, foot_pos.z);
+        let ground_height = match ground.0.height(ground_pos) {
+            Some(height) => height,
+            None => continue,
+        };
+        let goal_pos = Vec3::new(foot_pos.x, ground_height + foot.foot_height, foot_pos.z);
+        foot.normal = ground.0.normal(ground_pos);
+        goal_tf.translation = goal_pos;
+        *goal_gt = GlobalTransform::from(*goal_tf);
+
+        // walk up the chain to the hip, measuring the leg length on the way
+        let mut leg_length = 0.;
+        let mut hip_id = goal.target_bone;
+        for _ in 0..goal.chain_length {
+            let par_id = match parents.get(hip_id) {
+                Ok(parent) => parent.get(),
+                Err(_) => break,
+            };
+            if let (Ok(bone_gt), Ok(par_gt)) = (global_tfs.get(hip_id), global_tfs.get(par_id)) {
+                leg_length += bone_gt.translation().distance(par_gt.translation());
+            }
+            hip_id = par_id;
+        }
+
+        // find the pelvis above the hip
+        let mut cur_id = hip_id;
+        let pelvis_id = loop {
+            if pelvises.contains(cur_id) {
+                break Some(cur_id);
+            }
+            match parents.get(cur_id) {
+                Ok(parent) => cur_id = parent.get(),
+                Err(_) => break None,
+            }
+        };
+        let (pelvis_id, pelvis) = match pelvis_id.map(|id| (id, pelvises.get(id))) {
+            Some((pelvis_id, Ok((_, pelvis, _)))) => (pelvis_id, pelvis),
+            _ => continue,
+        };
+
+        // the hip without the offset of the last frame
+        let hip_pos = global_tfs.get(hip_id).unwrap().translation()
+            - contained_offsets
+                .get(&pelvis_id)
+                .copied()
+                .unwrap_or(Vec3::ZERO);
+        let offset = pelvis_offset(hip_pos, goal_pos, leg_length * pelvis.max_extension);
+        pelvis_offsets.push((pelvis_id, offset));
+    }
+
+    // lower each pelvis by the largest offset needed by its feet
+    for (pelvis_id, mut pelvis, mut pelvis_tf) in pelvises.iter_mut() {
+        let offset = pelvis_offsets
+            .iter()
+            .filter(|(id, _)| *id == pelvis_id)
+            .map(|(_, offset)| *offset)
+            .fold(0., f32::min);
+
+        // the offset is global, move it into the space of the parent
+        let original = pelvis_tf.translation;
+        let local_offset = match parents
+            .get(pelvis_id)
+            .map(|parent| global_tfs.get(parent.get()))
+        {
+            Ok(Ok(par_gt)) => par_gt
+                .affine()
+                .inverse()
+                .transform_vector3(Vec3::Y * offset),
+            _ => Vec3::Y * offset,
+        };
+        pelvis_tf.translation += local_offset;
+        pelvis.offset = offset;
+        pelvis.last_translation = Some((original, pelvis_tf.translation));
+    }
+}
+
+/// The vertical offset (never positive) the hip has to be moved by, so a leg of the given length can reach the goal.
+pub fn pelvis_offset(hip_pos: Vec3, goal_pos: Vec3, leg_length: f32) -> f32 {
+    let to_goal = goal_pos - hip_pos;
+    let horizontal_sq = to_goal.x * to_goal.x + to_goal.z * to_goal.z;
+    let reach_sq = leg_length * leg_length;
+    if horizontal_sq >= reach_sq {
+        // out of reach horizontally, lowering the hip to the height of the goal gets the foot as close as possible
+        return to_goal.y.min(0.);
+    }
+    let vertical_reach = (reach_sq - horizontal_sq).sqrt();
+    (to_goal.y + vertical_reach).min(0.)
+}
+
+/// Rotates the foot bones, so their up axis follows the ground normal. Runs after the solver, so the rotations
+/// of the legs are final.
+pub fn align_feet(
+    mut feet: Query<(&IkGoal, &mut FootPlacement)>,
+    mut bones: Query<&mut Transform, With<Bone>>,
+    global_tfs: Query<&GlobalTransform, Without<Bone>>,
+    parents: Query<&Parent>,
+) {
+    for (goal, mut foot) in feet.iter_mut() {
+        let mut foot_tf = match bones.get_mut(goal.target_bone) {
+            Ok(foot_tf) => foot_tf,
+            Err(_) => continue,
+        };
+
+        // restore the rotation before the last alignment, unless somebody else has rotated the foot since
+        if let Some((original, applied)) = foot.last_alignment.take() {
+            if applied == foot_tf.rotation {
+                foot_tf.rotation = original;
+            }
+        }
+        if !foot.align_to_normal {
+            continue;
+        }
+
+        // the global transforms of the legs are not propagated yet, so the parent transform is built from local ones
+        let mut par_affine = Affine3A::IDENTITY;
+        let mut cur_id = goal.target_bone;
+        while let Ok(parent) = parents.get(cur_id) {
+            cur_id = parent.get();
+            if let Ok(par_tf) = bones.get(cur_id) {
+                par_affine = par_tf.compute_affine() * par_affine;
+            } else {
+                if let Ok(par_gt) = global_tfs.get(cur_id) {
+                    par_affine = par_gt.affine() * par_affine;
+                }
+                break;
+            }
+        }
+
+        let mut foot_tf = bones.get_mut(goal.target_bone).unwrap();
+        let original = foot_tf.rotation;
+        // swing the foot by the shortest arc, so it keeps its heading. The normal is moved into the space of the
+        // parent, so scaled parents don't tilt the foot.
+        let up = (original * foot.up).normalize();
+        let normal = par_affine
+            .inverse()
+            .transform_vector3(foot.normal)
+            .normalize();
+        foot_tf.rotation = (Quat::from_rotation_arc(up, normal) * original).normalize();
+        foot.last_alignment = Some((original, foot_tf.rotation));
+    }
+}
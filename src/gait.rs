@@ -0,0 +1,168 @@
+use crate::{components::PLACEHOLDER_ENTITY, ground::Ground, IkSystem};
+use bevy::{
+    ecs::{
+        entity::{EntityMap, MapEntities, MapEntitiesError},
+        reflect::ReflectMapEntities,
+    },
+    prelude::*,
+    transform::TransformSystem,
+};
+use std::f32::consts::PI;
+
+/// Moves the [`IkGoal`](crate::IkGoal)s of [`Leg`]s procedurally, so multi-legged creatures walk along with their
+/// [`Gait`] body. If there is a [`Ground`] resource, the legs step onto the ground.
+pub struct GaitPlugin;
+
+impl Plugin for GaitPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Gait>()
+            .register_type::<Leg>()
+            .register_type::<Option<Vec3>>()
+            .register_type::<(Vec3, Vec3, f32)>()
+            .register_type::<Option<(Vec3, Vec3, f32)>>()
+            // the body has moved during the update, its global transform is needed for the rest points
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                step_legs
+                    .label(IkSystem::Gait)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(IkSystem::Solve),
+            );
+    }
+}
+
+/// The body of a legged creature. Its legs are divided into groups, only one group steps at a time
+/// and the groups take turns, e.g. the diagonal leg pairs of a quadruped or the alternating tripods of an insect.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component)]
+pub struct Gait {
+    /// distance between a foot and its rest point which triggers a step
+    pub step_distance: f32,
+    /// duration of a step in seconds
+    pub step_duration: f32,
+    /// height of the arc of a step
+    pub step_height: f32,
+    /// number of leg groups
+    pub groups: usize,
+    /// the group which may step, maintained by the plugin. The next group takes over once the active group
+    /// has finished its steps, or if none of its legs has to step while another group waits.
+    pub active_group: usize,
+}
+
+impl Default for Gait {
+    fn default() -> Self {
+        Self {
+            step_distance: 0.3,
+            step_duration: 0.25,
+            step_height: 0.1,
+            groups: 2,
+            active_group: 0,
+        }
+    }
+}
+
+/// Moves the [`IkGoal`](crate::IkGoal) on the same entity like the foot of a walking leg. The foot stays planted
+/// until it is too far from its rest point, then it steps to the rest point. The goal entity must not have a parent.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct Leg {
+    /// the entity with the [`Gait`] this leg belongs to
+    pub body: Entity,
+    /// the rest point of the foot in the space of the body. On the ground, its y component is the height of the foot
+    /// above the ground instead.
+    pub rest_offset: Vec3,
+    /// the group of legs this leg steps with
+    pub group: usize,
+    /// the global position of the planted foot, maintained by the plugin
+    pub planted: Option<Vec3>,
+    /// start, target and progress (0 to 1) of the current step, maintained by the plugin
+    pub step: Option<(Vec3, Vec3, f32)>,
+}
+
+impl FromWorld for Leg {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            body: PLACEHOLDER_ENTITY,
+            rest_offset: Vec3::ZERO,
+            group: 0,
+            planted: None,
+            step: None,
+        }
+    }
+}
+
+impl MapEntities for Leg {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.body = entity_map.get(self.body)?;
+        Ok(())
+    }
+}
+
+pub fn step_legs(
+    mut bodies: Query<(Entity, &mut Gait, &GlobalTransform)>,
+    mut legs: Query<(&mut Leg, &mut Transform, &mut GlobalTransform), Without<Gait>>,
+    ground: Option<Res<Ground>>,
+    time: Res<Time>,
+) {
+    for (body_id, mut gait, body_gt) in bodies.iter_mut() {
+        let mut active_group_steps = false;
+        let mut active_group_landed = false;
+        let mut other_group_waits = false;
+
+        for (mut leg, mut goal_tf, mut goal_gt) in legs.iter_mut() {
+            if leg.body != body_id {
+                continue;
+            }
+
+            // the rest point follows the body, on the ground if there is any
+            let mut rest_pos = body_gt.transform_point(leg.rest_offset);
+            if let Some(height) = ground
+                .as_ref()
+                .and_then(|ground| ground.0.height(Vec2::new(rest_pos.x, rest_pos.z)))
+            {
+                rest_pos.y = height + leg.rest_offset.y;
+            }
+            let planted = *leg.planted.get_or_insert(rest_pos);
+
+            // only the legs of the active group may start a step, the others wait for their turn
+            if leg.step.is_none() && planted.distance(rest_pos) > gait.step_distance {
+                match leg.group == gait.active_group {
+                    true => leg.step = Some((planted, rest_pos, 0.)),
+                    false => other_group_waits = true,
+                }
+            }
+
+            let foot_pos = match leg.step {
+                Some((start, target, progress)) => {
+                    let progress = match gait.step_duration > 0. {
+                        true => progress + time.delta_seconds() / gait.step_duration,
+                        false => 1.,
+                    };
+                    if progress >= 1. {
+                        leg.planted = Some(target);
+                        leg.step = None;
+                        active_group_landed |= leg.group == gait.active_group;
+                    } else {
+                        leg.step = Some((start, target, progress));
+                        active_group_steps |= leg.group == gait.active_group;
+                    }
+                    step_arc(start, target, gait.step_height, progress.min(1.))
+                }
+                None => planted,
+            };
+            goal_tf.translation = foot_pos;
+            *goal_gt = GlobalTransform::from(*goal_tf);
+        }
+
+        // once the active group has landed, the next group may step. An idle group passes its turn to waiting ones.
+        if !active_group_steps && (active_group_landed || other_group_waits) {
+            gait.active_group = (gait.active_group + 1) % gait.groups.max(1);
+        }
+    }
+}
+
+/// The position of a foot stepping from `start` to `target` with the given progress (0 to 1), on an arc
+/// of the given height.
+pub fn step_arc(start: Vec3, target: Vec3, height: f32, progress: f32) -> Vec3 {
+    start.lerp(target, progress) + Vec3::Y * height * (progress * PI).sin()
+}
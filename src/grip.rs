@@ -0,0 +1,220 @@
+use crate::{
+    components::{IkGoal, PLACEHOLDER_ENTITY},
+    IkSystem,
+};
+use bevy::{
+    ecs::{
+        entity::{EntityMap, MapEntities, MapEntitiesError},
+        reflect::ReflectMapEntities,
+    },
+    prelude::*,
+    transform::TransformSystem,
+};
+
+/// Moves the [`IkGoal`]s of [`GripFinger`]s onto the surface of their [`Grip`], so the fingertips touch it.
+/// The finger chains are solved together with all other goals, so fingers branching off the same palm joint,
+/// chain lengths and [`BoneStiffness`](crate::BoneStiffness) are handled like for any other chain.
+/// Only the fingertips are placed on the shape, the other finger joints are not checked against it and may end up
+/// inside of it, e.g. when the shape is large compared to the fingers. Stiff finger bones bend less towards it.
+pub struct GripPlugin;
+
+impl Plugin for GripPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Grip>()
+            .register_type::<GripFinger>()
+            .register_type::<GripShape>()
+            .register_type::<Vec<Vec3>>()
+            // the grip and the fingers have moved during the update, so their global transforms are needed
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                place_grip_goals
+                    .label(IkSystem::Grip)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(IkSystem::Solve),
+            );
+    }
+}
+
+/// A shape to grab, in the space of the entity it is inserted on, e.g. the handle of a weapon.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct Grip {
+    pub shape: GripShape,
+}
+
+#[derive(Clone, Debug, Reflect, FromReflect)]
+pub enum GripShape {
+    /// a sphere around the origin
+    Sphere { radius: f32 },
+    /// a capped cylinder around the y axis, from `-half_height` to `half_height`
+    Cylinder { radius: f32, half_height: f32 },
+    /// a box around the origin
+    Box { half_extents: Vec3 },
+    /// fixed contact points, one per finger, see [`GripFinger::contact`]
+    Contacts(Vec<Vec3>),
+}
+
+impl Default for GripShape {
+    fn default() -> Self {
+        Self::Sphere { radius: 0.05 }
+    }
+}
+
+impl GripShape {
+    /// The point of the surface closest to `point`, and the outward surface normal there. Contacts have no
+    /// surface, the given contact point is returned without a normal.
+    pub fn closest_point(&self, point: Vec3, contact: usize) -> Option<(Vec3, Vec3)> {
+        match self {
+            GripShape::Sphere { radius } => {
+                let normal = point.try_normalize().unwrap_or(Vec3::Y);
+                Some((normal * *radius, normal))
+            }
+            GripShape::Cylinder {
+                radius,
+                half_height,
+            } => {
+                let radial = Vec3::new(point.x, 0., point.z);
+                if radial.length() > *radius || point.y.abs() > *half_height {
+                    let closest = radial.clamp_length_max(*radius)
+                        + Vec3::Y * point.y.clamp(-half_height, *half_height);
+                    return Some((closest, (point - closest).normalize()));
+                }
+                // inside, push the point out through the closest side or cap
+                if radius - radial.length() < half_height - point.y.abs() {
+                    let normal = radial.try_normalize().unwrap_or(Vec3::X);
+                    Some((normal * *radius + Vec3::Y * point.y, normal))
+                } else {
+                    let normal = Vec3::Y * point.y.signum();
+                    Some((radial + normal * *half_height, normal))
+                }
+            }
+            GripShape::Box { half_extents } => {
+                let closest = point.clamp(-*half_extents, *half_extents);
+                if closest != point {
+                    return Some((closest, (point - closest).normalize()));
+                }
+                // inside, push the point out through the closest face
+                let depth = *half_extents - point.abs();
+                let axis = match depth.min_element() {
+                    d if d == depth.x => Vec3::X,
+                    d if d == depth.y => Vec3::Y,
+                    _ => Vec3::Z,
+                };
+                let normal = axis * point.dot(axis).signum();
+                Some((point + normal * depth.dot(axis), normal))
+            }
+            GripShape::Contacts(contacts) => {
+                contacts.get(contact).map(|contact| (*contact, Vec3::ZERO))
+            }
+        }
+    }
+}
+
+/// Moves the [`IkGoal`] on the same entity onto the surface of a [`Grip`], at the point closest to the fingertip.
+/// The target bone of the goal is the fingertip. The goal entity must not have a parent.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct GripFinger {
+    /// the entity with the [`Grip`]
+    pub grip: Entity,
+    /// the index of the contact point of this finger, if the grip shape is [`GripShape::Contacts`]
+    pub contact: usize,
+    /// distance between the fingertip bone and the surface, e.g. the thickness of the finger pad
+    pub pad: f32,
+}
+
+impl FromWorld for GripFinger {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            grip: PLACEHOLDER_ENTITY,
+            contact: 0,
+            pad: 0.,
+        }
+    }
+}
+
+impl MapEntities for GripFinger {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        // the grabbed object might live outside of the scene, in which case it is not mapped
+        if let Ok(mapped_entity) = entity_map.get(self.grip) {
+            self.grip = mapped_entity;
+        }
+        Ok(())
+    }
+}
+
+pub fn place_grip_goals(
+    mut fingers: Query<(&IkGoal, &GripFinger, &mut Transform, &mut GlobalTransform)>,
+    grips: Query<(&Grip, &GlobalTransform), Without<GripFinger>>,
+    global_tfs: Query<&GlobalTransform, Without<GripFinger>>,
+) {
+    for (goal, finger, mut goal_tf, mut goal_gt) in fingers.iter_mut() {
+        let ((grip, grip_gt), tip_gt) =
+            match (grips.get(finger.grip), global_tfs.get(goal.target_bone)) {
+                (Ok(grip), Ok(tip_gt)) => (grip, tip_gt),
+                _ => continue,
+            };
+
+        // find the closest point in the space of the grip
+        let tip_local = grip_gt
+            .affine()
+            .inverse()
+            .transform_point3(tip_gt.translation());
+        let (closest, normal) = match grip.shape.closest_point(tip_local, finger.contact) {
+            Some(closest) => closest,
+            None => continue,
+        };
+
+        // the pad is measured in global units, the grip might be scaled
+        let normal = grip_gt
+            .affine()
+            .transform_vector3(normal)
+            .normalize_or_zero();
+        goal_tf.translation = grip_gt.transform_point(closest) + normal * finger.pad;
+        *goal_gt = GlobalTransform::from(*goal_tf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f32 = 0.001;
+
+    #[test]
+    fn cylinder_closest_points() {
+        let shape = GripShape::Cylinder {
+            radius: 1.,
+            half_height: 2.,
+        };
+        let side = shape.closest_point(Vec3::new(3., 1., 0.), 0).unwrap();
+        assert_eq!(side, (Vec3::new(1., 1., 0.), Vec3::X));
+        let cap = shape.closest_point(Vec3::new(0.5, 3., 0.), 0).unwrap();
+        assert_eq!(cap, (Vec3::new(0.5, 2., 0.), Vec3::Y));
+        let inside = shape.closest_point(Vec3::new(0., -1.5, 0.8), 0).unwrap();
+        assert!(inside.0.distance(Vec3::new(0., -1.5, 1.)) < TOLERANCE);
+        assert_eq!(inside.1, Vec3::Z);
+    }
+
+    #[test]
+    fn box_closest_points() {
+        let shape = GripShape::Box {
+            half_extents: Vec3::new(1., 2., 3.),
+        };
+        let outside = shape.closest_point(Vec3::new(2., 0., 0.), 0).unwrap();
+        assert_eq!(outside, (Vec3::new(1., 0., 0.), Vec3::X));
+        let inside = shape.closest_point(Vec3::new(0.5, -1.8, 0.), 0).unwrap();
+        assert!(inside.0.distance(Vec3::new(0.5, -2., 0.)) < TOLERANCE);
+        assert_eq!(inside.1, -Vec3::Y);
+    }
+
+    #[test]
+    fn contacts_are_picked_by_index() {
+        let shape = GripShape::Contacts(vec![Vec3::X, Vec3::Y]);
+        assert_eq!(
+            shape.closest_point(Vec3::ZERO, 1),
+            Some((Vec3::Y, Vec3::ZERO))
+        );
+        assert_eq!(shape.closest_point(Vec3::ZERO, 2), None);
+    }
+}
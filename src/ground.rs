@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+
+/// Answers ground queries for the [`FootPlacementPlugin`](crate::FootPlacementPlugin), without depending on
+/// a physics engine. Implemented for [`FlatGround`], [`Heightfield`] and closures `Fn(Vec2) -> f32`.
+/// Positions are horizontal positions (x, z) in global space.
+pub trait GroundProvider: Send + Sync + 'static {
+    /// The height of the ground at a position, or `None` if there is no ground.
+    fn height(&self, pos: Vec2) -> Option<f32>;
+
+    /// The ground normal at a position, estimated from the heights around it by default.
+    fn normal(&self, pos: Vec2) -> Vec3 {
+        const EPS: f32 = 0.01;
+        let height = |pos: Vec2| self.height(pos).unwrap_or_default();
+        let dx = height(pos + Vec2::X * EPS) - height(pos - Vec2::X * EPS);
+        let dz = height(pos + Vec2::Y * EPS) - height(pos - Vec2::Y * EPS);
+        Vec3::new(-dx, 2. * EPS, -dz).normalize()
+    }
+}
+
+impl<F> GroundProvider for F
+where
+    F: Fn(Vec2) -> f32 + Send + Sync + 'static,
+{
+    fn height(&self, pos: Vec2) -> Option<f32> {
+        Some(self(pos))
+    }
+}
+
+/// An infinite horizontal plane.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct FlatGround {
+    pub height: f32,
+}
+
+impl GroundProvider for FlatGround {
+    fn height(&self, _pos: Vec2) -> Option<f32> {
+        Some(self.height)
+    }
+
+    fn normal(&self, _pos: Vec2) -> Vec3 {
+        Vec3::Y
+    }
+}
+
+/// A regular grid of height samples, interpolated bilinearly. There is no ground outside of the grid.
+#[derive(Default, Debug, Clone)]
+pub struct Heightfield {
+    /// position of the first sample
+    pub origin: Vec2,
+    /// distance between two neighbouring samples
+    pub spacing: f32,
+    /// number of samples along x
+    pub columns: usize,
+    /// the height samples, row by row along z
+    pub heights: Vec<f32>,
+}
+
+impl Heightfield {
+    fn sample(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.columns + column]
+    }
+}
+
+impl GroundProvider for Heightfield {
+    fn height(&self, pos: Vec2) -> Option<f32> {
+        if self.columns < 2 || self.heights.len() < 2 * self.columns {
+            return None;
+        }
+        let rows = self.heights.len() / self.columns;
+
+        // the cell containing the position, and the position inside of the cell
+        let grid_pos = (pos - self.origin) / self.spacing;
+        let max = Vec2::new((self.columns - 1) as f32, (rows - 1) as f32);
+        if grid_pos.cmplt(Vec2::ZERO).any() || grid_pos.cmpgt(max).any() {
+            return None;
+        }
+        let cell = grid_pos.floor().min(max - Vec2::ONE);
+        let (column, row) = (cell.x as usize, cell.y as usize);
+        let t = grid_pos - cell;
+
+        let near = self.sample(column, row) * (1. - t.x) + self.sample(column + 1, row) * t.x;
+        let far =
+            self.sample(column, row + 1) * (1. - t.x) + self.sample(column + 1, row + 1) * t.x;
+        Some(near * (1. - t.y) + far * t.y)
+    }
+}
+
+/// The ground used by the [`FootPlacementPlugin`](crate::FootPlacementPlugin). Defaults to a [`FlatGround`]
+/// at height 0.
+#[derive(Resource)]
+pub struct Ground(pub Box<dyn GroundProvider>);
+
+impl Ground {
+    pub fn new(provider: impl GroundProvider) -> Self {
+        Self(Box::new(provider))
+    }
+}
+
+impl Default for Ground {
+    fn default() -> Self {
+        Self::new(FlatGround::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f32 = 0.001;
+
+    fn heightfield() -> Heightfield {
+        // 3 x 2 samples, rising along x
+        Heightfield {
+            origin: Vec2::new(-1., -1.),
+            spacing: 1.,
+            columns: 3,
+            heights: vec![0., 1., 2., 0., 1., 2.],
+        }
+    }
+
+    #[test]
+    fn heightfield_interpolates_samples() {
+        let ground = heightfield();
+        assert_eq!(ground.height(Vec2::new(-1., -1.)), Some(0.));
+        assert_eq!(ground.height(Vec2::new(1., 0.)), Some(2.));
+        assert!((ground.height(Vec2::new(0.5, -0.5)).unwrap() - 1.5).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn heightfield_has_no_ground_outside() {
+        let ground = heightfield();
+        assert_eq!(ground.height(Vec2::new(-1.5, 0.)), None);
+        assert_eq!(ground.height(Vec2::new(0., 0.5)), None);
+    }
+
+    #[test]
+    fn closure_normal_follows_slope() {
+        let ground = |pos: Vec2| pos.x;
+        let normal = ground.normal(Vec2::ZERO);
+        assert!(normal.distance(Vec3::new(-1., 1., 0.).normalize()) < TOLERANCE);
+        assert_eq!(FlatGround { height: 2. }.normal(Vec2::ZERO), Vec3::Y);
+    }
+}
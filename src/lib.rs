@@ -1,22 +1,82 @@
 //! bevy_ik is a inverse kinematics solver as a bevy plugin.
 #![forbid(unsafe_code)] // let us try
 
+mod commands;
 mod components;
+mod foot_placement;
+mod gait;
+mod grip;
+mod ground;
+mod retarget;
+#[cfg(feature = "rig_asset")]
+mod rig;
+#[cfg(feature = "skinning")]
+mod skinning;
+mod solver;
+mod spline;
 mod systems;
 
-use bevy::prelude::*;
-use components::{ArmatureGraph, IkData, IkSettings};
+use bevy::{
+    prelude::*,
+    transform::{transform_propagate_system, TransformSystem},
+    utils::HashMap,
+};
+use spline::apply_spline_ik;
 use systems::*;
 
 // reexports
-pub use components::{Bone, BoneBundle, IkGoal, IkGoalBundle};
+pub use commands::{CaptureRestPose, ResetToRestPose, RestPoseCommands};
+pub use components::{
+    ArmatureBones, ArmatureGraph, Bone, BoneBundle, BoneIndex, BoneMass, BoneRef, BoneStiffness,
+    IkData, IkGoal, IkGoalAnchor, IkGoalBundle, IkGoalLink, IkGoalTarget, IkGoalTargetBundle,
+    IkSettings, IkSmoothing, Planted, RestPose, RootMotion, TwistHelper, TwistSource,
+};
+pub use foot_placement::{FootPlacement, FootPlacementPlugin, Pelvis};
+pub use gait::{Gait, GaitPlugin, Leg};
+pub use grip::{Grip, GripFinger, GripPlugin, GripShape};
+pub use ground::{FlatGround, Ground, GroundProvider, Heightfield};
+pub use retarget::{Retarget, RetargetContact, RetargetPlugin};
+#[cfg(feature = "rig_asset")]
+pub use rig::{IkRig, IkRigAnchor, IkRigBone, IkRigGoal, IkRigInstance, IkRigLoader, IkRigPlugin};
+#[cfg(feature = "skinning")]
+pub use skinning::{SkinnedMeshBonesPlugin, SkinnedMeshBonesSettings};
+pub use solver::{Chains, JointGoal, Pose, Skeleton};
+pub use spline::{SplineCurve, SplineIk};
+// the solver systems, so they can be run and measured on their own
+pub use systems::{
+    cache_ik_data, compute_joint_positions, create_armature_tree, index_bones,
+    resolve_goal_positions,
+};
 
 pub const DEFAULT_GOAL_TOLERANCE: f32 = 0.0001;
 pub const DEFAULT_MAX_ITERATIONS: u32 = 100;
 
+/// Labels for the systems of the [`InverseKinematicsPlugin`]. All of them run in [`CoreStage::PostUpdate`].
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub enum IkSystem {
+    /// Places the foot goals on the ground, see [`FootPlacementPlugin`].
+    FootPlacement,
+    /// Moves the foot goals of walking legs, see [`GaitPlugin`].
+    Gait,
+    /// Moves the fingertip goals onto the grabbed shapes, see [`GripPlugin`].
+    Grip,
+    /// Copies the poses of source armatures onto target armatures, see [`RetargetPlugin`].
+    Retarget,
+    /// Solves all goals, after the transforms of this frame have been propagated.
+    Solve,
+    /// Propagates the solved bone transforms, so they are visible in the same frame.
+    Propagate,
+}
+
 pub struct InverseKinematicsPlugin {
     pub goal_tolerance: f32,
     pub max_iterations: u32,
+    /// start solving from the previous frame's solution instead of the current bone positions
+    pub warm_start: bool,
+    /// which twist solved bones keep, see [`TwistSource`]
+    pub twist_source: TwistSource,
+    /// solve whole armatures and let their roots move, see [`IkSettings::full_body`]
+    pub full_body: bool,
 }
 
 impl Default for InverseKinematicsPlugin {
@@ -24,6 +84,9 @@ impl Default for InverseKinematicsPlugin {
         Self {
             goal_tolerance: DEFAULT_GOAL_TOLERANCE,
             max_iterations: DEFAULT_MAX_ITERATIONS,
+            warm_start: false,
+            twist_source: TwistSource::default(),
+            full_body: false,
         }
     }
 }
@@ -33,12 +96,71 @@ impl Plugin for InverseKinematicsPlugin {
         app.insert_resource(IkSettings {
             goal_tolerance: self.goal_tolerance,
             max_iterations: self.max_iterations,
+            warm_start: self.warm_start,
+            twist_source: self.twist_source,
+            full_body: self.full_body,
         })
         .init_resource::<ArmatureGraph>()
         .init_resource::<IkData>()
-        .add_system(create_armature_tree)
-        .add_system(cache_ik_data.after(create_armature_tree))
-        .add_system(compute_joint_positions.after(cache_ik_data))
-        .add_system(apply_bone_rotations.after(compute_joint_positions));
+        .init_resource::<BoneIndex>()
+        .register_type::<Bone>()
+        .register_type::<BoneMass>()
+        .register_type::<BoneRef>()
+        .register_type::<BoneStiffness>()
+        .register_type::<IkGoal>()
+        .register_type::<IkGoalAnchor>()
+        .register_type::<IkGoalLink>()
+        .register_type::<IkGoalTarget>()
+        .register_type::<IkSmoothing>()
+        .register_type::<Planted>()
+        .register_type::<RestPose>()
+        .register_type::<RootMotion>()
+        .register_type::<SplineCurve>()
+        .register_type::<SplineIk>()
+        .register_type::<TwistHelper>()
+        // field types of the components, needed to load them from scenes
+        .register_type::<HashMap<Entity, Transform>>()
+        .register_type::<HashMap<Entity, f32>>()
+        .register_type::<HashMap<Entity, Quat>>()
+        .register_type::<HashMap<Entity, (Quat, Quat)>>()
+        .register_type::<Option<f32>>()
+        .register_type::<Option<Entity>>()
+        .register_type::<(Vec3, Vec3)>()
+        .register_type::<Option<(Vec3, Vec3)>>()
+        .register_type::<(Quat, Quat)>()
+        .register_type::<Option<(Quat, Quat)>>()
+        .register_type::<Vec<Entity>>()
+        .register_type::<IkSettings>()
+        .register_type::<ArmatureGraph>()
+        .register_type::<IkData>()
+        .register_type::<BoneIndex>()
+        // root bones are moved back before the character controllers consume their root motion
+        .add_system_to_stage(CoreStage::PreUpdate, reset_root_motion)
+        // new armatures are captured before anything in this frame modifies them, e.g. the animation player
+        .add_system_to_stage(CoreStage::PostUpdate, capture_rest_poses.at_start())
+        // goals may follow other entities, so we solve after their global transforms are up to date
+        .add_system_set_to_stage(
+            CoreStage::PostUpdate,
+            SystemSet::new()
+                .label(IkSystem::Solve)
+                .after(TransformSystem::TransformPropagate)
+                .with_system(index_bones)
+                .with_system(resolve_goal_targets.after(index_bones))
+                .with_system(create_armature_tree.after(index_bones))
+                .with_system(cache_ik_data.after(create_armature_tree))
+                .with_system(resolve_goal_positions.after(cache_ik_data))
+                .with_system(compute_joint_positions.after(resolve_goal_positions))
+                .with_system(apply_root_motion.after(compute_joint_positions))
+                .with_system(apply_bone_rotations.after(apply_root_motion))
+                .with_system(apply_spline_ik.after(apply_bone_rotations))
+                .with_system(smooth_bone_rotations.after(apply_spline_ik))
+                .with_system(distribute_twist.after(smooth_bone_rotations)),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            transform_propagate_system
+                .label(IkSystem::Propagate)
+                .after(IkSystem::Solve),
+        );
     }
 }
@@ -0,0 +1,245 @@
+use crate::{
+    components::{
+        Bone, BoneIndex, BoneRef, IkData, IkGoal, IkGoalBundle, RestPose, PLACEHOLDER_ENTITY,
+    },
+    IkSystem,
+};
+use bevy::{
+    ecs::{
+        entity::{EntityMap, MapEntities, MapEntitiesError},
+        reflect::ReflectMapEntities,
+    },
+    prelude::*,
+    transform::{transform_propagate_system, TransformSystem},
+    utils::HashMap,
+};
+
+/// Copies the poses of source armatures onto target armatures with different proportions, see [`Retarget`].
+pub struct RetargetPlugin;
+
+impl Plugin for RetargetPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Retarget>()
+            .register_type::<RetargetContact>()
+            .register_type::<HashMap<String, String>>()
+            .register_type::<Vec<RetargetContact>>()
+            // the target bones are moved before solving, so their transforms have to be propagated again
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .label(IkSystem::Retarget)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(IkSystem::Solve)
+                    .with_system(retarget_poses)
+                    .with_system(transform_propagate_system.after(retarget_poses)),
+            );
+    }
+}
+
+/// Copies the pose of a source armature onto the armature with this root bone. Mapped bones are rotated
+/// like their source bones, relative to the [`RestPose`]s of both armatures. The translation of the root bone
+/// is scaled by the size of the target armature relative to the source armature.
+/// Limbs of different lengths don't end up where the source limbs are, the [`RetargetContact`]s are moved there
+/// by the solver instead, e.g. feet on the ground or hands on a prop.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct Retarget {
+    /// the root bone of the source armature
+    pub source: Entity,
+    /// names of the target bones and the names of the source bones they copy
+    pub bone_map: HashMap<String, String>,
+    /// target bones which are kept at the positions of their source bones. The goals of contacts which are removed
+    /// or can't be resolved are despawned, like all goals once the component is removed.
+    pub contacts: Vec<RetargetContact>,
+}
+
+impl FromWorld for Retarget {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            source: PLACEHOLDER_ENTITY,
+            bone_map: HashMap::new(),
+            contacts: Vec::new(),
+        }
+    }
+}
+
+impl MapEntities for Retarget {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.source = entity_map.get(self.source)?;
+        for contact in self.contacts.iter_mut() {
+            if let Some(goal) = contact.goal {
+                contact.goal = entity_map.get(goal).ok();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A mapped target bone, which is moved to the position of its source bone by an [`IkGoal`].
+#[derive(Clone, Debug, Default, Reflect, FromReflect)]
+pub struct RetargetContact {
+    /// name of the target bone
+    pub bone: String,
+    pub chain_length: u32,
+    /// the goal moving the bone, maintained by the plugin
+    pub goal: Option<Entity>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn retarget_poses(
+    mut commands: Commands,
+    mut retargets: Query<(Entity, &mut Retarget)>,
+    mut bones: Query<&mut Transform, With<Bone>>,
+    mut goals: Query<&mut Transform, (With<IkGoal>, Without<Bone>)>,
+    global_tfs: Query<(&GlobalTransform, Option<&Parent>), Without<IkGoal>>,
+    rest_poses: Query<&RestPose>,
+    index: Res<BoneIndex>,
+    data: Res<IkData>,
+    removed: RemovedComponents<Retarget>,
+    mut spawned_goals: Local<HashMap<Entity, Vec<Entity>>>,
+) {
+    // the goals of removed retargets aren't needed anymore
+    for target_root in removed.iter() {
+        for goal_id in spawned_goals.remove(&target_root).unwrap_or_default() {
+            if let Some(goal) = commands.get_entity(goal_id) {
+                goal.despawn_recursive();
+            }
+        }
+    }
+
+    for (target_root, mut retarget) in retargets.iter_mut() {
+        let (source_rest, target_rest) =
+            match (rest_poses.get(retarget.source), rest_poses.get(target_root)) {
+                (Ok(source_rest), Ok(target_rest)) => (source_rest, target_rest),
+                _ => continue,
+            };
+
+        // mapped bones, as pairs of target and source bone
+        let source_root = retarget.source;
+        let pairs: Vec<(Entity, Entity)> = retarget
+            .bone_map
+            .iter()
+            .filter_map(|(target_name, source_name)| {
+                Some((
+                    index.get(target_root, &BoneRef::Name(target_name.clone()))?,
+                    index.get(source_root, &BoneRef::Name(source_name.clone()))?,
+                ))
+            })
+            .collect();
+
+        // the size of the target armature relative to the source armature, from the lengths of the mapped bones
+        let (target_length, source_length) = pairs
+            .iter()
+            .filter_map(|(target_id, source_id)| {
+                Some((
+                    *data.bone_lengths.get(target_id)?,
+                    *data.bone_lengths.get(source_id)?,
+                ))
+            })
+            .fold((0., 0.), |(t, s), (target, source)| {
+                (t + target, s + source)
+            });
+        let scale = match source_length > 0. {
+            true => target_length / source_length,
+            false => 1.,
+        };
+
+        // copy the rotations relative to the rest poses
+        for (target_id, source_id) in pairs.iter() {
+            let (source_tf, source_rest_tf, target_rest_tf) = match (
+                bones.get(*source_id),
+                source_rest.transforms.get(source_id),
+                target_rest.transforms.get(target_id),
+            ) {
+                (Ok(source_tf), Some(source_rest_tf), Some(target_rest_tf)) => {
+                    (*source_tf, source_rest_tf, target_rest_tf)
+                }
+                _ => continue,
+            };
+            let mut target_tf = match bones.get_mut(*target_id) {
+                Ok(target_tf) => target_tf,
+                Err(_) => continue,
+            };
+            target_tf.rotation =
+                (target_rest_tf.rotation * source_rest_tf.rotation.inverse() * source_tf.rotation)
+                    .normalize();
+            if *target_id == target_root {
+                target_tf.translation = target_rest_tf.translation
+                    + (source_tf.translation - source_rest_tf.translation) * scale;
+            }
+        }
+
+        // the space the armatures are placed in, the contacts keep their positions relative to it
+        let armature_space = |root_id: Entity| match global_tfs.get(root_id) {
+            Ok((_, Some(parent))) => global_tfs
+                .get(parent.get())
+                .map_or(GlobalTransform::IDENTITY, |(par_gt, _)| *par_gt),
+            _ => GlobalTransform::IDENTITY,
+        };
+        let to_target_space =
+            armature_space(target_root).affine() * armature_space(source_root).affine().inverse();
+
+        let retarget = &mut *retarget;
+        for contact in retarget.contacts.iter_mut() {
+            let resolved = retarget
+                .bone_map
+                .get(&contact.bone)
+                .and_then(|source_name| {
+                    Some((
+                        index.get(target_root, &BoneRef::Name(contact.bone.clone()))?,
+                        index.get(source_root, &BoneRef::Name(source_name.clone()))?,
+                    ))
+                })
+                .and_then(|(target_id, source_id)| {
+                    let (source_gt, _) = global_tfs.get(source_id).ok()?;
+                    Some((target_id, source_gt.translation()))
+                });
+            let (target_id, source_pos) = match resolved {
+                Some(resolved) => resolved,
+                None => {
+                    // the goal of a contact which can't be resolved would keep moving its bone
+                    if let Some(goal) = contact.goal.take().and_then(|id| commands.get_entity(id)) {
+                        goal.despawn_recursive();
+                    }
+                    continue;
+                }
+            };
+            let position = to_target_space.transform_point3(source_pos);
+
+            // the goals are propagated along with the target bones
+            match contact.goal.and_then(|goal_id| goals.get_mut(goal_id).ok()) {
+                Some(mut goal_tf) => goal_tf.translation = position,
+                None => {
+                    let goal_id = commands
+                        .spawn(IkGoalBundle {
+                            goal: IkGoal {
+                                target_bone: target_id,
+                                chain_length: contact.chain_length,
+                            },
+                            transform: Transform::from_translation(position),
+                            global_transform: GlobalTransform::from_translation(position),
+                        })
+                        .id();
+                    contact.goal = Some(goal_id);
+                }
+            }
+        }
+
+        // despawn the goals of contacts which were removed from the list
+        let contact_goals: Vec<Entity> = retarget
+            .contacts
+            .iter()
+            .filter_map(|contact| contact.goal)
+            .collect();
+        let spawned = spawned_goals.entry(target_root).or_default();
+        for goal_id in spawned.drain(..) {
+            if contact_goals.contains(&goal_id) {
+                continue;
+            }
+            if let Some(goal) = commands.get_entity(goal_id) {
+                goal.despawn_recursive();
+            }
+        }
+        *spawned = contact_goals;
+    }
+}
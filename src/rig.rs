@@ -0,0 +1,304 @@
+use crate::components::{
+    Bone, BoneIndex, BoneMass, BoneRef, BoneStiffness, IkGoal, IkGoalAnchor, IkGoalBundle, Planted,
+};
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+/// Describes the goals of a skeleton and the settings of its bones, keyed by bone names.
+/// The bones are looked up in the [`BoneIndex`] of the armatures below the rig entity, names of multiple bones are
+/// ambiguous and keep the rig from being applied. The solver has no pole targets, joint angle constraints or goal
+/// weights, so rigs can't describe them. Bone stiffness and mass are the only bone settings.
+/// Loaded from `.ikrig.ron` files:
+///
+/// ```ron
+/// (
+///     goals: [
+///         (bone: "bone_hand.L", chain_length: 2, anchor: Some((bone: "bone_hand.R", offset: (0.0, 0.3, 0.0)))),
+///         (bone: "bone_hand.R", chain_length: 2, position: Some((0.3, 1.2, 0.0))),
+///         (bone: "bone_foot.L", chain_length: 2, planted: true),
+///     ],
+///     bones: [
+///         (bone: "bone_spine", stiffness: Some(0.5), mass: Some(12.0)),
+///     ],
+/// )
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "6b7a2c1e-3f0d-4a52-9e61-8d4c5f0b2a17"]
+pub struct IkRig {
+    pub goals: Vec<IkRigGoal>,
+    #[serde(default)]
+    pub bones: Vec<IkRigBone>,
+}
+
+impl IkRig {
+    /// The names of all bones the rig refers to.
+    pub fn bone_names(&self) -> impl Iterator<Item = &str> {
+        self.goals
+            .iter()
+            .flat_map(|goal| {
+                [
+                    Some(goal.bone.as_str()),
+                    goal.anchor.as_ref().map(|anchor| anchor.bone.as_str()),
+                ]
+            })
+            .flatten()
+            .chain(self.bones.iter().map(|bone| bone.bone.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IkRigGoal {
+    /// name of the target [`Bone`]
+    pub bone: String,
+    pub chain_length: u32,
+    /// initial global position of the goal, defaults to the position of the target bone
+    #[serde(default)]
+    pub position: Option<Vec3>,
+    /// makes the goal follow another bone of the rig, see [`IkGoalAnchor`]
+    #[serde(default)]
+    pub anchor: Option<IkRigAnchor>,
+    /// see [`Planted`]
+    #[serde(default)]
+    pub planted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IkRigAnchor {
+    /// name of the [`Bone`] the goal follows
+    pub bone: String,
+    /// translation of the goal in the space of the bone
+    #[serde(default)]
+    pub offset: Vec3,
+}
+
+/// Settings of a single bone, inserted as components on the bone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IkRigBone {
+    /// name of the [`Bone`]
+    pub bone: String,
+    /// see [`BoneStiffness`]
+    #[serde(default)]
+    pub stiffness: Option<f32>,
+    /// see [`BoneMass`]
+    #[serde(default)]
+    pub mass: Option<f32>,
+}
+
+/// Added to an entity with a `Handle<IkRig>` once the rig has been applied to the bones below it.
+/// Holds the spawned goals and the bones the rig has inserted components on, so they can be replaced
+/// when the rig asset changes.
+#[derive(Component)]
+pub struct IkRigInstance {
+    pub goals: Vec<Entity>,
+    pub bones: Vec<Entity>,
+}
+
+#[derive(Default)]
+pub struct IkRigLoader;
+
+impl AssetLoader for IkRigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let rig = ron::de::from_bytes::<IkRig>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(rig));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ikrig.ron"]
+    }
+}
+
+/// Loads [`IkRig`] assets and applies them to entities with a `Handle<IkRig>`, e.g. the root of a spawned scene.
+/// Requires the [`AssetPlugin`] and the [`InverseKinematicsPlugin`](crate::InverseKinematicsPlugin).
+pub struct IkRigPlugin;
+
+impl Plugin for IkRigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<IkRig>()
+            .init_asset_loader::<IkRigLoader>()
+            .add_system(reload_ik_rigs)
+            .add_system(apply_ik_rigs.after(reload_ik_rigs));
+    }
+}
+
+pub fn apply_ik_rigs(
+    mut commands: Commands,
+    rigs: Query<(Entity, &Handle<IkRig>), Without<IkRigInstance>>,
+    rig_assets: Res<Assets<IkRig>>,
+    children: Query<&Children>,
+    bones: Query<(&Bone, &GlobalTransform)>,
+    index: Res<BoneIndex>,
+    mut warned: Local<HashSet<(Entity, String)>>,
+) {
+    for (rig_id, handle) in rigs.iter() {
+        let rig = match rig_assets.get(handle) {
+            Some(rig) => rig,
+            None => continue, // not loaded yet
+        };
+
+        // gather the armatures below the rig entity
+        let mut armatures = Vec::new();
+        let mut todo_stack = vec![rig_id];
+        while let Some(entity) = todo_stack.pop() {
+            if index.armatures.contains_key(&entity) {
+                armatures.push(entity);
+            } else if let Ok(entity_children) = children.get(entity) {
+                todo_stack.extend(entity_children.iter());
+            }
+        }
+
+        // look up the bones by name. A name found in multiple armatures is ambiguous, like a name of multiple bones
+        // of one armature, which the index leaves out.
+        let mut bone_ids = HashMap::<&str, Entity>::new();
+        for name in rig.bone_names() {
+            let bone_ref = BoneRef::Name(name.to_string());
+            let matches: Vec<Entity> = armatures
+                .iter()
+                .filter_map(|armature| index.get(*armature, &bone_ref))
+                .filter(|bone_id| bones.contains(*bone_id))
+                .collect();
+            match matches[..] {
+                [bone_id] => {
+                    bone_ids.insert(name, bone_id);
+                }
+                [] => {}
+                _ => {
+                    if warned.insert((rig_id, name.to_string())) {
+                        warn!(
+                            "Multiple armatures below the rig entity {:?} have a bone named {:?}, the rig can't be applied",
+                            rig_id, name
+                        );
+                    }
+                }
+            }
+        }
+
+        // the scene might not be spawned yet, or its bones might be tagged later, try again next frame
+        if rig.bone_names().any(|name| !bone_ids.contains_key(name)) {
+            continue;
+        }
+
+        let mut goals = Vec::new();
+        for rig_goal in rig.goals.iter() {
+            let target_id = bone_ids[rig_goal.bone.as_str()];
+            let (_, bone_tf) = bones.get(target_id).unwrap();
+            let position = rig_goal.position.unwrap_or(bone_tf.translation());
+
+            let mut goal = commands.spawn(IkGoalBundle {
+                goal: IkGoal {
+                    target_bone: target_id,
+                    chain_length: rig_goal.chain_length,
+                },
+                transform: Transform::from_translation(position),
+                global_transform: GlobalTransform::from_translation(position),
+            });
+            if let Some(anchor) = &rig_goal.anchor {
+                goal.insert(IkGoalAnchor {
+                    entity: bone_ids[anchor.bone.as_str()],
+                    offset: Transform::from_translation(anchor.offset),
+                });
+            }
+            if rig_goal.planted {
+                goal.insert(Planted);
+            }
+            goals.push(goal.id());
+        }
+
+        let mut rig_bones = Vec::new();
+        for rig_bone in rig.bones.iter() {
+            let bone_id = bone_ids[rig_bone.bone.as_str()];
+            let mut bone = commands.entity(bone_id);
+            if let Some(stiffness) = rig_bone.stiffness {
+                bone.insert(BoneStiffness(stiffness));
+            }
+            if let Some(mass) = rig_bone.mass {
+                bone.insert(BoneMass(mass));
+            }
+            rig_bones.push(bone_id);
+        }
+
+        commands.entity(rig_id).insert(IkRigInstance {
+            goals,
+            bones: rig_bones,
+        });
+    }
+}
+
+/// When a rig asset is modified, its goals are despawned, the bone settings are removed and the rig is applied again.
+pub fn reload_ik_rigs(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<IkRig>>,
+    rigs: Query<(Entity, &Handle<IkRig>, &IkRigInstance)>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            for (rig_id, rig_handle, instance) in rigs.iter() {
+                if rig_handle != handle {
+                    continue;
+                }
+                for goal_id in instance.goals.iter() {
+                    commands.entity(*goal_id).despawn_recursive();
+                }
+                for bone_id in instance.bones.iter() {
+                    commands
+                        .entity(*bone_id)
+                        .remove::<(BoneStiffness, BoneMass)>();
+                }
+                commands.entity(rig_id).remove::<IkRigInstance>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RIG: &str = r#"(
+        goals: [
+            (bone: "hand.L", chain_length: 2, anchor: Some((bone: "hand.R", offset: (0.0, 0.3, 0.0)))),
+            (bone: "hand.R", chain_length: 2, position: Some((0.3, 1.2, 0.0))),
+            (bone: "foot.L", chain_length: 3, planted: true),
+        ],
+        bones: [
+            (bone: "spine", stiffness: Some(0.5), mass: Some(12.0)),
+        ],
+    )"#;
+
+    #[test]
+    fn rig_round_trip() {
+        let rig: IkRig = ron::from_str(RIG).unwrap();
+        assert_eq!(rig.goals.len(), 3);
+        assert_eq!(rig.goals[0].position, None);
+        assert_eq!(
+            rig.goals[0].anchor,
+            Some(IkRigAnchor {
+                bone: "hand.R".to_string(),
+                offset: Vec3::Y * 0.3,
+            })
+        );
+        assert!(rig.goals[2].planted && !rig.goals[1].planted);
+        assert_eq!(rig.bones[0].stiffness, Some(0.5));
+
+        let serialized = ron::to_string(&rig).unwrap();
+        assert_eq!(ron::from_str::<IkRig>(&serialized).unwrap(), rig);
+    }
+
+    #[test]
+    fn bones_are_optional() {
+        let rig: IkRig = ron::from_str(r#"(goals: [(bone: "hand", chain_length: 2)])"#).unwrap();
+        assert!(rig.bones.is_empty());
+        assert_eq!(rig.bone_names().collect::<Vec<_>>(), ["hand"]);
+    }
+}
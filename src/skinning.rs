@@ -0,0 +1,56 @@
+use crate::components::Bone;
+use bevy::{prelude::*, render::mesh::skinning::SkinnedMesh};
+
+/// Tags the joints of every [`SkinnedMesh`] with a [`Bone`] component, so imported rigs (e.g. from glTF)
+/// can be solved without tagging their bones by hand.
+/// Bones are named after the [`Name`] of the joint entity.
+#[derive(Default)]
+pub struct SkinnedMeshBonesPlugin {
+    /// if set, only joints whose name contains this string are tagged
+    pub name_filter: Option<String>,
+}
+
+#[derive(Default, Resource)]
+pub struct SkinnedMeshBonesSettings {
+    pub name_filter: Option<String>,
+}
+
+impl Plugin for SkinnedMeshBonesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SkinnedMeshBonesSettings {
+            name_filter: self.name_filter.clone(),
+        })
+        .add_system(tag_skinned_mesh_bones);
+    }
+}
+
+/// A [`SkinnedMesh`] is inserted once its scene instance has been spawned, so all its joints exist at this point.
+pub fn tag_skinned_mesh_bones(
+    mut commands: Commands,
+    skins: Query<&SkinnedMesh, Added<SkinnedMesh>>,
+    names: Query<&Name>,
+    bones: Query<(), With<Bone>>,
+    settings: Res<SkinnedMeshBonesSettings>,
+) {
+    for skin in skins.iter() {
+        for &joint_id in skin.joints.iter() {
+            // multiple meshes can share the same skin joints
+            if bones.contains(joint_id) {
+                continue;
+            }
+
+            let name = match names.get(joint_id) {
+                Ok(name) => name.to_string(),
+                Err(_) => format!("{:?}", joint_id),
+            };
+
+            if let Some(filter) = &settings.name_filter {
+                if !name.contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            commands.entity(joint_id).insert(Bone { name });
+        }
+    }
+}
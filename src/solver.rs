@@ -0,0 +1,878 @@
+//! The FABRIK solver, independent of the ECS. It works on joint ids and global joint positions only,
+//! so it can be used from tools, servers and tests without a [`World`](bevy::ecs::world::World).
+//! The systems of the [`InverseKinematicsPlugin`](crate::InverseKinematicsPlugin) build a [`Skeleton`] from the bones
+//! of all armatures and solve it once per frame.
+use crate::components::IkSettings;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use std::collections::VecDeque;
+
+/// number of alternating projections onto the balance and reach constraints in full body mode
+const BALANCE_ITERATIONS: usize = 4;
+
+/// Global joint positions by joint id.
+pub type Pose = HashMap<u32, Vec3>;
+
+/// The joint tree of one or more armatures. A joint is between two bones, each joint except the roots is the end
+/// of exactly one bone.
+#[derive(Default, Debug, Clone, Reflect)]
+pub struct Skeleton {
+    /// maps joints to their parent joint
+    pub joint_parent: HashMap<u32, u32>,
+    /// length of the bone ending at each joint
+    pub bone_lengths: HashMap<u32, f32>,
+    /// mass of the bone ending at each joint, used for balancing in full body mode. Defaults to the bone length.
+    pub masses: HashMap<u32, f32>,
+    /// armature roots which may move towards the goals, and the maximum distance they may move by
+    pub max_root_offsets: HashMap<u32, f32>,
+    /// stiffness of the bone ending at each joint, from 0 (rotates freely, the default) to 1 (keeps its direction)
+    pub stiffness: HashMap<u32, f32>,
+}
+
+/// A joint which should be moved to a position, by moving at most `chain_length` joints above it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct JointGoal {
+    pub joint: u32,
+    pub chain_length: u32,
+    pub position: Vec3,
+    /// planted goals (e.g. feet on the ground) span the support polygon for balancing in full body mode
+    pub planted: bool,
+    /// the joint of another goal, which keeps its position relative to this goal, e.g. two hands holding a rifle.
+    /// If one of the goals can't be reached, both are moved by the same offset, so they share the error.
+    pub linked: Option<u32>,
+}
+
+/// The parts of a [`Skeleton`] which are solved for a set of goals.
+#[derive(Default, Debug, Clone, Reflect)]
+pub struct Chains {
+    /// for each joint, which children joints do we need info from? (some joints might not have IK goals)
+    pub required_positions: HashMap<u32, HashSet<u32>>,
+    /// FABRIK roots - joints defined by not having a parent, or by chain length, or if fixed
+    pub roots: HashSet<u32>,
+}
+
+impl Chains {
+    /// Whether the bone ending at `joint` is solved.
+    pub fn contains(&self, joint: u32) -> bool {
+        self.required_positions
+            .values()
+            .any(|reqs| reqs.contains(&joint))
+    }
+}
+
+impl Skeleton {
+    /// Adds a joint at the end of a bone of the given length, starting at `parent`.
+    pub fn add_joint(&mut self, joint: u32, parent: u32, bone_length: f32) -> &mut Self {
+        self.joint_parent.insert(joint, parent);
+        self.bone_lengths.insert(joint, bone_length);
+        self
+    }
+
+    /// Walks up the tree from each goal joint to find the chains which have to be solved.
+    /// Takes the goal joints and their chain lengths.
+    pub fn chains(&self, goals: impl IntoIterator<Item = (u32, u32)>) -> Chains {
+        let mut chains = Chains::default();
+
+        for (goal_joint, chain_length) in goals {
+            // a goal without a chain can't move any joint, its joint is its own root
+            if chain_length == 0 {
+                chains.roots.insert(goal_joint);
+                continue;
+            }
+            let mut cur_id = goal_joint;
+            for i in 0..chain_length {
+                if let Some(par_id) = self.joint_parent.get(&cur_id) {
+                    // add the child bone as a required bone for the parent bone
+                    chains
+                        .required_positions
+                        .entry(*par_id)
+                        .or_default()
+                        .insert(cur_id);
+                    cur_id = *par_id;
+                } else {
+                    // bone without parent, this is the root
+                    chains.roots.insert(cur_id);
+                    break;
+                }
+
+                //if we stop going up the tree due to chain length limitation, this node now also counts as a pseudo-root
+                if i == chain_length - 1 {
+                    chains.roots.insert(cur_id);
+                }
+            }
+        }
+
+        // a pseudo-root inside of a longer chain of another goal is solved as part of that chain
+        let inner_roots: Vec<u32> = chains
+            .roots
+            .iter()
+            .filter(|root| {
+                self.joint_parent
+                    .get(root)
+                    .and_then(|par_id| chains.required_positions.get(par_id))
+                    .is_some_and(|reqs| reqs.contains(root))
+            })
+            .copied()
+            .collect();
+        for root in inner_roots {
+            chains.roots.remove(&root);
+        }
+
+        chains
+    }
+
+    /// Moves the joints of `pose` towards the goals. Roots and joints outside of the chains keep their positions,
+    /// except for the armature roots in full body mode, see [`IkSettings::full_body`].
+    pub fn solve(&self, pose: &mut Pose, goals: &[JointGoal], settings: &IkSettings) {
+        let chains = self.chains(
+            goals
+                .iter()
+                .map(|goal| (goal.joint, settings.chain_length(goal.chain_length))),
+        );
+        self.solve_chains(&chains, pose, goals, settings);
+    }
+
+    /// Whether the solver may move the joint. Only armature roots may move, in full body mode or if they have
+    /// a maximum root offset.
+    pub fn is_movable_root(&self, joint: u32, settings: &IkSettings) -> bool {
+        !self.joint_parent.contains_key(&joint)
+            && (settings.full_body || self.max_root_offsets.contains_key(&joint))
+    }
+
+    /// Walks up the tree to the root joint of each joint.
+    fn armature_roots(&self) -> HashMap<u32, u32> {
+        self.bone_lengths
+            .keys()
+            .map(|joint| {
+                let mut root = *joint;
+                while let Some(par_id) = self.joint_parent.get(&root) {
+                    root = *par_id;
+                }
+                (*joint, root)
+            })
+            .collect()
+    }
+
+    /// The global center of mass of all bones below the given root joint.
+    fn center_of_mass(
+        &self,
+        root: u32,
+        armature_roots: &HashMap<u32, u32>,
+        pose: &Pose,
+    ) -> Option<Vec3> {
+        let mut weighted_sum = Vec3::ZERO;
+        let mut total_mass = 0.;
+        for (joint, bone_length) in self.bone_lengths.iter() {
+            if armature_roots.get(joint) != Some(&root) {
+                continue;
+            }
+            let mass = self.masses.get(joint).copied().unwrap_or(*bone_length);
+            let par_pos = pose.get(self.joint_parent.get(joint)?)?;
+            weighted_sum += (*pose.get(joint)? + *par_pos) * 0.5 * mass;
+            total_mass += mass;
+        }
+        (total_mass > 0.).then(|| weighted_sum / total_mass)
+    }
+
+    /// Moves the proposed position of an armature root, so the center of mass of the armature stays
+    /// above the support polygon of its planted goals, and keeps the root within reach of the planted goals,
+    /// so the armature can't float away from them. The armature is assumed to move along with its root.
+    fn balance(
+        &self,
+        root: u32,
+        mut root_pos: Vec3,
+        armature_roots: &HashMap<u32, u32>,
+        pose: &Pose,
+        goals: &[JointGoal],
+    ) -> Vec3 {
+        let planted: Vec<&JointGoal> = goals
+            .iter()
+            .filter(|goal| goal.planted && armature_roots.get(&goal.joint) == Some(&root))
+            .collect();
+        if planted.is_empty() {
+            return root_pos;
+        }
+        let hull = convex_hull(
+            planted
+                .iter()
+                .map(|goal| Vec2::new(goal.position.x, goal.position.z))
+                .collect(),
+        );
+        let com = match self.center_of_mass(root, armature_roots, pose) {
+            Some(com) => com - *pose.get(&root).unwrap(),
+            None => return root_pos,
+        };
+        let reaches: Vec<f32> = planted
+            .iter()
+            .map(|goal| self.chain_reach(goal.joint, root))
+            .collect();
+
+        // both constraints move the root, alternate between them to approach a position satisfying both
+        for _ in 0..BALANCE_ITERATIONS {
+            let cur_com = com + root_pos;
+            let cur_com = Vec2::new(cur_com.x, cur_com.z);
+            let correction = closest_point_in_polygon(cur_com, &hull) - cur_com;
+            root_pos += Vec3::new(correction.x, 0., correction.y);
+
+            for (goal, reach) in planted.iter().zip(reaches.iter()) {
+                let to_root = root_pos - goal.position;
+                if to_root.length() > *reach {
+                    root_pos = goal.position + to_root.normalize() * *reach;
+                }
+            }
+        }
+        root_pos
+    }
+
+    /// The summed length of the bones between a joint and one of its ancestors.
+    fn chain_reach(&self, mut joint: u32, ancestor: u32) -> f32 {
+        let mut reach = 0.;
+        while joint != ancestor {
+            reach += self.bone_lengths.get(&joint).copied().unwrap_or_default();
+            joint = match self.joint_parent.get(&joint) {
+                Some(par_id) => *par_id,
+                None => break,
+            };
+        }
+        reach
+    }
+
+    /// Whether the goal is within reach of its chain, measured from the base joint of the chain in the given pose.
+    /// Movable roots add their maximum offset to the reach.
+    fn in_reach(&self, goal: &JointGoal, pose: &Pose, settings: &IkSettings) -> bool {
+        let mut base = goal.joint;
+        for _ in 0..settings.chain_length(goal.chain_length) {
+            match self.joint_parent.get(&base) {
+                Some(par_id) => base = *par_id,
+                None => break,
+            }
+        }
+        let base_pos = match pose.get(&base) {
+            Some(base_pos) => *base_pos,
+            None => return true,
+        };
+        let root_offset = match self.is_movable_root(base, settings) {
+            true => self
+                .max_root_offsets
+                .get(&base)
+                .copied()
+                .unwrap_or(f32::INFINITY),
+            false => 0.,
+        };
+        base_pos.distance(goal.position) <= self.chain_reach(goal.joint, base) + root_offset
+    }
+
+    /// Like [`Skeleton::solve`], with chains computed beforehand by [`Skeleton::chains`] for the same goals.
+    pub fn solve_chains(
+        &self,
+        chains: &Chains,
+        pose: &mut Pose,
+        goals: &[JointGoal],
+        settings: &IkSettings,
+    ) {
+        let mut goal_positions: HashMap<u32, Vec3> = goals
+            .iter()
+            .map(|goal| (goal.joint, goal.position))
+            .collect();
+
+        // pairs of linked goals, each pair once. If both goals are within reach, there is no error to share.
+        let mut links: Vec<(u32, u32)> = goals
+            .iter()
+            .filter_map(|goal| {
+                let other = goals
+                    .iter()
+                    .find(|other| Some(other.joint) == goal.linked)?;
+                let shared =
+                    !self.in_reach(goal, pose, settings) || !self.in_reach(other, pose, settings);
+                shared.then(|| (goal.joint.min(other.joint), goal.joint.max(other.joint)))
+            })
+            .filter(|(joint, other)| joint != other)
+            .collect();
+        links.sort_unstable();
+        links.dedup();
+
+        let armature_roots = match settings.full_body {
+            true => self.armature_roots(),
+            false => HashMap::new(),
+        };
+
+        // movable roots are limited by their offset from the position before solving, not the one of the last iteration
+        let root_starts: HashMap<u32, Vec3> = self
+            .max_root_offsets
+            .keys()
+            .filter_map(|joint| Some((*joint, *pose.get(joint)?)))
+            .collect();
+
+        // stiff bones are rotated less, relative to their direction before solving
+        let start_dirs: HashMap<u32, Vec3> = self
+            .stiffness
+            .keys()
+            .filter_map(|joint| {
+                let par_pos = pose.get(self.joint_parent.get(joint)?)?;
+                Some((*joint, *pose.get(joint)? - *par_pos))
+            })
+            .collect();
+
+        // queue to walk through the joint tree
+        let mut todo_queue = VecDeque::<u32>::new();
+
+        for _ in 0..settings.max_iterations {
+            // check if target joints are close enough to the goals
+            let mut highest_dist: f32 = 0.0;
+            for (goal_joint, goal_pos) in goal_positions.iter() {
+                let pos = pose.get(goal_joint).unwrap();
+                let dist = (*goal_pos - *pos).length();
+                highest_dist = highest_dist.max(dist);
+            }
+            if highest_dist < settings.goal_tolerance {
+                break;
+            }
+            /*
+             * FORWARD PASS - LEAF TO ROOT
+             */
+
+            // initialize todo queue with starting joints (joints with goals)
+            todo_queue.clear();
+            todo_queue.extend(goal_positions.keys());
+
+            // new positions
+            let mut new_positions = Pose::new();
+
+            // actual forward pass
+            while let Some(joint_id) = todo_queue.pop_front() {
+                // check if all required joint children have a new position computed, otherwise push this joint back into the queue
+                let mut ready = true;
+                if let Some(reqs) = chains.required_positions.get(&joint_id) {
+                    for req_id in reqs {
+                        if !new_positions.contains_key(req_id) {
+                            ready = false;
+                            break;
+                        }
+                    }
+                }
+                if !ready {
+                    todo_queue.push_back(joint_id);
+                    continue;
+                }
+
+                // figure out the new forward position for this joint
+                if let Some(goal_pos) = goal_positions.get(&joint_id) {
+                    // in the forward pass, the target joint of the goal is simply set to the goal position
+                    new_positions.insert(joint_id, *goal_pos);
+                } else {
+                    // otherwise compute a new position for each child
+                    // the new position is the centroid of those positions
+                    let old_pos = pose.get(&joint_id).unwrap();
+                    let children = chains.required_positions.get(&joint_id).unwrap();
+                    let mut new_pos_centroid = Vec3::ZERO;
+                    for child_id in children {
+                        let child_link_length = self.bone_lengths.get(child_id).unwrap();
+
+                        let new_child_pos = new_positions.get(child_id).unwrap();
+                        // keep the old bone direction if the child ended up on top of this joint
+                        let old_child_pos = pose.get(child_id).unwrap();
+                        let dir = (*old_pos - *new_child_pos)
+                            .try_normalize()
+                            .unwrap_or_else(|| (*old_pos - *old_child_pos).normalize())
+                            * *child_link_length;
+                        let new_pos = *new_child_pos + dir;
+                        new_pos_centroid += new_pos;
+                    }
+                    new_pos_centroid *= 1. / children.len() as f32;
+                    new_positions.insert(joint_id, new_pos_centroid);
+                }
+
+                // if we are not at the root, push the parent to the todo_queue, if it's not already in there
+                if !chains.roots.contains(&joint_id) {
+                    if let Some(par_id) = self.joint_parent.get(&joint_id) {
+                        if !todo_queue.contains(par_id) {
+                            todo_queue.push_back(*par_id);
+                        }
+                    }
+                }
+            }
+
+            /*
+             * BACKWARD PASS - ROOT TO LEAF
+             */
+
+            // prepare todo queue for backward pass
+            todo_queue.clear();
+            todo_queue.extend(chains.roots.iter());
+
+            // actual backward pass
+            while let Some(joint_id) = todo_queue.pop_front() {
+                // if this joint is one of the roots or pseudo-roots, we just set it back to its original position.
+                // Movable armature roots follow the forward pass instead, as long as the armature stays balanced
+                // in full body mode, and within their maximum offset
+                if chains.roots.contains(&joint_id) {
+                    let old_pos = *pose.get(&joint_id).unwrap();
+                    let pos = match new_positions.get(&joint_id) {
+                        Some(forward_pos) if self.is_movable_root(joint_id, settings) => {
+                            let mut pos = *forward_pos;
+                            if settings.full_body {
+                                pos = self.balance(joint_id, pos, &armature_roots, pose, goals);
+                            }
+                            if let (Some(max_offset), Some(start)) = (
+                                self.max_root_offsets.get(&joint_id),
+                                root_starts.get(&joint_id),
+                            ) {
+                                pos = *start + (pos - *start).clamp_length_max(*max_offset);
+                            }
+                            pos
+                        }
+                        _ => old_pos,
+                    };
+                    new_positions.insert(joint_id, pos);
+                } else {
+                    let bone_length = self.bone_lengths.get(&joint_id).unwrap();
+                    let par_id = self.joint_parent.get(&joint_id).unwrap();
+                    let par_pos = new_positions.get(par_id).unwrap();
+                    let forward_pos = new_positions.get(&joint_id).unwrap();
+                    // keep the old bone direction if the forward position ended up on top of the parent
+                    let old_pos = pose.get(&joint_id).unwrap();
+                    let old_par_pos = pose.get(par_id).unwrap();
+                    let mut dir = (*forward_pos - *par_pos)
+                        .try_normalize()
+                        .unwrap_or_else(|| (*old_pos - *old_par_pos).normalize());
+                    if let (Some(stiffness), Some(start_dir)) = (
+                        self.stiffness.get(&joint_id),
+                        start_dirs
+                            .get(&joint_id)
+                            .and_then(|dir| dir.try_normalize()),
+                    ) {
+                        let rotation = Quat::from_rotation_arc(start_dir, dir);
+                        dir = Quat::IDENTITY.slerp(rotation, 1. - stiffness.clamp(0., 1.))
+                            * start_dir;
+                    }
+                    let backward_pos = *par_pos + dir * *bone_length;
+                    new_positions.insert(joint_id, backward_pos);
+                }
+
+                // put all required children in the todo queue - those who lead to a leaf joint with IK goal
+                if let Some(children) = chains.required_positions.get(&joint_id) {
+                    todo_queue.extend(children.iter());
+                }
+            }
+
+            // "flip the buffer" - joints outside of the chains keep their positions
+            pose.extend(new_positions);
+
+            // linked goals out of reach move together by their mean error, which keeps their relative position
+            // and shares the error between both chains
+            for (joint, other) in links.iter() {
+                let error =
+                    |joint: &u32| *pose.get(joint).unwrap() - *goal_positions.get(joint).unwrap();
+                let shift = (error(joint) + error(other)) * 0.5;
+                *goal_positions.get_mut(joint).unwrap() += shift;
+                *goal_positions.get_mut(other).unwrap() += shift;
+            }
+        }
+    }
+}
+
+/// The convex hull of the points in counter-clockwise order (x to z), by Andrew's monotone chain algorithm.
+fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: Vec2, a: Vec2, b: Vec2| (a - o).perp_dot(b - o);
+    let mut hull = Vec::<Vec2>::new();
+    // lower hull, then upper hull
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // the last point is the first point of the next pass
+        hull.pop();
+    }
+    hull
+}
+
+/// The point of a convex polygon (or of a segment or a point) closest to `point`.
+fn closest_point_in_polygon(point: Vec2, hull: &[Vec2]) -> Vec2 {
+    let closest_on_segment = |a: Vec2, b: Vec2| {
+        let t = ((point - a).dot(b - a) / (b - a).length_squared()).clamp(0., 1.);
+        a + (b - a) * t
+    };
+    match hull.len() {
+        0 => point,
+        1 => hull[0],
+        2 => closest_on_segment(hull[0], hull[1]),
+        len => {
+            let edges = (0..len).map(|i| (hull[i], hull[(i + 1) % len]));
+            if edges
+                .clone()
+                .all(|(a, b)| (b - a).perp_dot(point - a) >= 0.)
+            {
+                return point;
+            }
+            edges
+                .map(|(a, b)| closest_on_segment(a, b))
+                .min_by(|a, b| {
+                    a.distance_squared(point)
+                        .total_cmp(&b.distance_squared(point))
+                })
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_GOAL_TOLERANCE, DEFAULT_MAX_ITERATIONS};
+
+    const TOLERANCE: f32 = 0.001;
+
+    fn settings() -> IkSettings {
+        IkSettings {
+            goal_tolerance: DEFAULT_GOAL_TOLERANCE,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            ..default()
+        }
+    }
+
+    /// a vertical chain of joints 0 to `num_joints - 1`, one unit apart
+    fn chain(num_joints: u32) -> (Skeleton, Pose) {
+        let mut skeleton = Skeleton::default();
+        let mut pose = Pose::new();
+        pose.insert(0, Vec3::ZERO);
+        for joint in 1..num_joints {
+            skeleton.add_joint(joint, joint - 1, 1.);
+            pose.insert(joint, Vec3::Y * joint as f32);
+        }
+        (skeleton, pose)
+    }
+
+    fn assert_bone_lengths(skeleton: &Skeleton, pose: &Pose) {
+        for (joint, length) in skeleton.bone_lengths.iter() {
+            let par_pos = pose[&skeleton.joint_parent[joint]];
+            assert!((pose[joint].distance(par_pos) - length).abs() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn solve_reachable_goal() {
+        let (skeleton, mut pose) = chain(3);
+        let goal = JointGoal {
+            joint: 2,
+            chain_length: 2,
+            position: Vec3::new(1., 1., 0.),
+            planted: false,
+            linked: None,
+        };
+        skeleton.solve(&mut pose, &[goal], &settings());
+
+        assert!(pose[&2].distance(goal.position) < TOLERANCE);
+        assert_eq!(pose[&0], Vec3::ZERO);
+        assert_bone_lengths(&skeleton, &pose);
+    }
+
+    #[test]
+    fn solve_unreachable_goal_stretches_chain() {
+        let (skeleton, mut pose) = chain(3);
+        let goal = JointGoal {
+            joint: 2,
+            chain_length: 2,
+            position: Vec3::X * 5.,
+            planted: false,
+            linked: None,
+        };
+        skeleton.solve(&mut pose, &[goal], &settings());
+
+        assert!(pose[&1].distance(Vec3::X) < TOLERANCE);
+        assert!(pose[&2].distance(Vec3::X * 2.) < TOLERANCE);
+    }
+
+    #[test]
+    fn solve_movable_root_within_limit() {
+        let (mut skeleton, pose) = chain(3);
+        skeleton.max_root_offsets.insert(0, 1.);
+        let goal = |position| JointGoal {
+            joint: 2,
+            chain_length: 2,
+            position,
+            planted: false,
+            linked: None,
+        };
+
+        // the root moves by just enough to reach the goal
+        let mut reachable = pose.clone();
+        skeleton.solve(&mut reachable, &[goal(Vec3::X * 2.5)], &settings());
+        assert!(reachable[&2].distance(Vec3::X * 2.5) < TOLERANCE);
+        assert!(reachable[&0].length() <= 1. + TOLERANCE);
+        assert_bone_lengths(&skeleton, &reachable);
+
+        // the root moves by the maximum offset towards a goal out of reach
+        let mut unreachable = pose;
+        skeleton.solve(&mut unreachable, &[goal(Vec3::X * 5.)], &settings());
+        assert!(unreachable[&0].distance(Vec3::X) < TOLERANCE);
+        assert!(unreachable[&2].distance(Vec3::X * 3.) < TOLERANCE);
+    }
+
+    #[test]
+    fn solve_stiff_bones_rotate_less() {
+        let (mut skeleton, pose) = chain(4);
+        let goal = JointGoal {
+            joint: 3,
+            chain_length: 3,
+            position: Vec3::new(1.8, 1.2, 0.),
+            planted: false,
+            linked: None,
+        };
+        let angle = |pose: &Pose| (pose[&1] - pose[&0]).angle_between(Vec3::Y);
+
+        let mut flexible = pose.clone();
+        skeleton.solve(&mut flexible, &[goal], &settings());
+
+        // a half stiff lower bone rotates less, the upper bones make up for it
+        skeleton.stiffness.insert(1, 0.5);
+        let mut stiff = pose.clone();
+        skeleton.solve(&mut stiff, &[goal], &settings());
+        assert!(stiff[&3].distance(goal.position) < TOLERANCE);
+        assert!(angle(&stiff) < 0.75 * angle(&flexible));
+        assert_bone_lengths(&skeleton, &stiff);
+
+        // a rigid lower bone keeps its direction
+        skeleton.stiffness.insert(1, 1.);
+        let mut rigid = pose;
+        skeleton.solve(&mut rigid, &[goal], &settings());
+        assert!(rigid[&3].distance(goal.position) < TOLERANCE);
+        assert!(rigid[&1].distance(Vec3::Y) < TOLERANCE);
+    }
+
+    #[test]
+    fn solve_linked_goals_share_error() {
+        // two arms of two bones, starting at the root
+        let mut skeleton = Skeleton::default();
+        let mut pose = Pose::new();
+        pose.insert(0, Vec3::ZERO);
+        for (joint, parent, pos) in [
+            (1, 0, Vec3::X),
+            (2, 1, Vec3::X * 2.),
+            (3, 0, -Vec3::X),
+            (4, 3, -Vec3::X * 2.),
+        ] {
+            skeleton.add_joint(joint, parent, 1.);
+            pose.insert(joint, pos);
+        }
+        let mut goals =
+            [(2, Vec3::new(1., 1., 0.)), (4, Vec3::new(-2.2, 1., 0.))].map(|(joint, position)| {
+                JointGoal {
+                    joint,
+                    chain_length: 2,
+                    position,
+                    planted: false,
+                    linked: None,
+                }
+            });
+
+        // unlinked, the right arm reaches its goal, the left arm can't
+        let mut unlinked = pose.clone();
+        skeleton.solve(&mut unlinked, &goals, &settings());
+        assert!(unlinked[&2].distance(goals[0].position) < TOLERANCE);
+        assert!(unlinked[&4].distance(goals[1].position) > 0.1);
+
+        // linked, both arms keep the distance of the goals and are off by the same error
+        goals[0].linked = Some(4);
+        let mut linked = pose;
+        skeleton.solve(&mut linked, &goals, &settings());
+        let offset = goals[1].position - goals[0].position;
+        assert!((linked[&4] - linked[&2]).distance(offset) < TOLERANCE);
+        let errors = [
+            linked[&2] - goals[0].position,
+            linked[&4] - goals[1].position,
+        ];
+        assert!(errors[0].distance(errors[1]) < TOLERANCE);
+        assert_bone_lengths(&skeleton, &linked);
+    }
+
+    #[test]
+    fn solve_linked_goals_within_reach() {
+        // two arms of three bones, starting at the root
+        let mut skeleton = Skeleton::default();
+        let mut pose = Pose::new();
+        pose.insert(0, Vec3::ZERO);
+        for (joint, parent, pos) in [
+            (1, 0, Vec3::X),
+            (2, 1, Vec3::X * 2.),
+            (3, 2, Vec3::X * 3.),
+            (4, 0, -Vec3::X),
+            (5, 4, -Vec3::X * 2.),
+            (6, 5, -Vec3::X * 3.),
+        ] {
+            skeleton.add_joint(joint, parent, 1.);
+            pose.insert(joint, pos);
+        }
+        let goals = [
+            (3, 6, Vec3::new(1., 1., 0.)),
+            (6, 3, Vec3::new(-1.2, 0.8, 0.5)),
+        ]
+        .map(|(joint, linked, position)| JointGoal {
+            joint,
+            chain_length: 3,
+            position,
+            planted: false,
+            linked: Some(linked),
+        });
+
+        // both goals are within reach, so the links don't move them
+        skeleton.solve(&mut pose, &goals, &settings());
+        for goal in goals.iter() {
+            assert!(pose[&goal.joint].distance(goal.position) < TOLERANCE);
+        }
+        assert_bone_lengths(&skeleton, &pose);
+    }
+
+    #[test]
+    fn solve_keeps_joints_outside_of_chain() {
+        let (skeleton, mut pose) = chain(4);
+        let goal = JointGoal {
+            joint: 3,
+            chain_length: 1,
+            position: Vec3::new(1., 2., 0.),
+            planted: false,
+            linked: None,
+        };
+        skeleton.solve(&mut pose, &[goal], &settings());
+
+        assert_eq!(pose[&0], Vec3::ZERO);
+        assert_eq!(pose[&1], Vec3::Y);
+        assert_eq!(pose[&2], Vec3::Y * 2.);
+        assert!(pose[&3].distance(goal.position) < TOLERANCE);
+    }
+
+    #[test]
+    fn solve_goal_without_chain() {
+        let (skeleton, mut pose) = chain(4);
+        let start = pose.clone();
+        let goals = [(3, 0), (2, 0), (3, 1)].map(|(joint, chain_length)| JointGoal {
+            joint,
+            chain_length,
+            position: Vec3::new(1., 2., 0.),
+            planted: false,
+            linked: None,
+        });
+
+        // on its own, the goal doesn't move any joint
+        skeleton.solve(&mut pose, &goals[..1], &settings());
+        assert_eq!(pose, start);
+
+        // the chain of another goal ending at its joint is solved as usual
+        skeleton.solve(&mut pose, &goals[1..], &settings());
+        assert_eq!(pose[&1], Vec3::Y);
+        assert!(pose[&3].distance(goals[2].position) < TOLERANCE);
+    }
+
+    /// a root with two bent legs and an arm, standing on the feet 0.4 apart
+    fn body() -> (Skeleton, Pose) {
+        let mut skeleton = Skeleton::default();
+        let mut pose = Pose::new();
+        pose.insert(0, Vec3::Y);
+        for (joint, parent, pos) in [
+            (5, 0, Vec3::new(-0.2, 0.5, 0.1)),
+            (1, 5, Vec3::new(-0.2, 0., 0.)),
+            (6, 0, Vec3::new(0.2, 0.5, 0.1)),
+            (2, 6, Vec3::new(0.2, 0., 0.)),
+            (3, 0, Vec3::new(0., 1.5, 0.)),
+            (4, 3, Vec3::new(0.5, 1.5, 0.)),
+        ] {
+            skeleton.add_joint(joint, parent, pos.distance(pose[&parent]));
+            pose.insert(joint, pos);
+        }
+        (skeleton, pose)
+    }
+
+    fn full_body_settings() -> IkSettings {
+        IkSettings {
+            full_body: true,
+            ..settings()
+        }
+    }
+
+    fn planted_feet(pose: &Pose) -> Vec<JointGoal> {
+        [1, 2]
+            .map(|joint| JointGoal {
+                joint,
+                chain_length: 2,
+                position: pose[&joint],
+                planted: true,
+                linked: None,
+            })
+            .to_vec()
+    }
+
+    #[test]
+    fn solve_full_body_moves_root() {
+        let (skeleton, mut pose) = body();
+        let goal = JointGoal {
+            joint: 4,
+            chain_length: 1,
+            position: Vec3::new(1.5, 1.5, 0.),
+            planted: false,
+            linked: None,
+        };
+        skeleton.solve(&mut pose, &[goal], &full_body_settings());
+
+        assert!(pose[&4].distance(goal.position) < TOLERANCE);
+        assert!(pose[&0].x > 0.5);
+    }
+
+    #[test]
+    fn solve_full_body_keeps_balance() {
+        let (skeleton, mut pose) = body();
+        let mut goals = planted_feet(&pose);
+        goals.push(JointGoal {
+            joint: 4,
+            chain_length: 1,
+            position: Vec3::new(1.5, 1.2, 0.),
+            planted: false,
+            linked: None,
+        });
+        skeleton.solve(&mut pose, &goals, &full_body_settings());
+
+        // the feet stay planted, the body leans towards the goal, but its center of mass stays between the feet.
+        // The hand goal is out of reach, so the solver stops at a compromise and the feet are a bit less precise.
+        assert!(pose[&1].distance(goals[0].position) < 10. * TOLERANCE);
+        assert!(pose[&2].distance(goals[1].position) < 10. * TOLERANCE);
+        assert!(pose[&0].x > 0.);
+        let com = skeleton
+            .center_of_mass(0, &skeleton.armature_roots(), &pose)
+            .unwrap();
+        assert!(com.x.abs() < 0.2 + TOLERANCE);
+        assert_bone_lengths(&skeleton, &pose);
+    }
+
+    #[test]
+    fn convex_hull_drops_inner_points() {
+        let square = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        let hull = convex_hull([square.to_vec(), vec![Vec2::splat(0.5)]].concat());
+        assert_eq!(hull.len(), 4);
+        assert!(square.iter().all(|point| hull.contains(point)));
+
+        assert_eq!(
+            closest_point_in_polygon(Vec2::splat(0.5), &hull),
+            Vec2::splat(0.5)
+        );
+        assert_eq!(
+            closest_point_in_polygon(Vec2::new(2., 0.5), &hull),
+            Vec2::new(1., 0.5)
+        );
+        assert_eq!(
+            closest_point_in_polygon(Vec2::new(2., 0.5), &hull[..2]),
+            hull[1]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! Spline IK: a chain of bones, e.g. a spine, a tail or a tentacle, follows a smooth curve through
+//! control entities instead of being solved by FABRIK.
+use crate::{
+    components::{Bone, IkSettings, RestPose, TwistSource, PLACEHOLDER_ENTITY},
+    systems::{aim_bone, twist_angle},
+};
+use bevy::{
+    ecs::{
+        entity::{EntityMap, MapEntities, MapEntitiesError},
+        reflect::ReflectMapEntities,
+    },
+    prelude::*,
+    utils::HashMap,
+};
+
+/// number of points each curve segment is sampled with
+const SAMPLES_PER_SEGMENT: usize = 16;
+
+/// Bends a chain of bones along a curve. The curve starts at the base of the chain and is shaped by the global
+/// translations of the control entities. Controls which don't exist (yet) are skipped, without any controls
+/// the chain is left alone. The bones keep their lengths, so the chain ends where the curve has the length
+/// of the chain, or continues straight beyond the end of the curve.
+/// The chain should not be part of an [`IkGoal`](crate::IkGoal) chain.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct SplineIk {
+    /// the last bone of the chain
+    pub tip_bone: Entity,
+    /// number of bones following the curve, including the tip bone
+    pub chain_length: u32,
+    /// the entities whose global translations shape the curve, from the base towards the tip
+    pub controls: Vec<Entity>,
+    pub curve: SplineCurve,
+    /// the axis of the last control in its local space. Its twist around this axis, relative to the parent of
+    /// the chain, is distributed along the chain.
+    pub twist_axis: Vec3,
+    /// the local rotation of each bone before and after the last frame, maintained by the plugin
+    pub last_rotations: HashMap<Entity, (Quat, Quat)>,
+}
+
+impl FromWorld for SplineIk {
+    fn from_world(_world: &mut World) -> Self {
+        Self {
+            tip_bone: PLACEHOLDER_ENTITY,
+            chain_length: 0,
+            controls: Vec::new(),
+            curve: SplineCurve::default(),
+            twist_axis: Vec3::Y,
+            last_rotations: HashMap::new(),
+        }
+    }
+}
+
+impl MapEntities for SplineIk {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.tip_bone = entity_map.get(self.tip_bone)?;
+        // controls might live outside of the scene, in which case they are not mapped
+        for control in self.controls.iter_mut() {
+            if let Ok(mapped_entity) = entity_map.get(*control) {
+                *control = mapped_entity;
+            }
+        }
+        self.last_rotations = self
+            .last_rotations
+            .drain()
+            .filter_map(|(bone_id, rotations)| Some((entity_map.get(bone_id).ok()?, rotations)))
+            .collect();
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Reflect, FromReflect)]
+pub enum SplineCurve {
+    /// passes through all control points
+    #[default]
+    CatmullRom,
+    /// a single Bezier curve, which only passes through the first and the last control point
+    Bezier,
+}
+
+impl SplineCurve {
+    /// Samples the curve through the given control points into a polyline.
+    pub fn sample(&self, points: &[Vec3]) -> Vec<Vec3> {
+        if points.len() < 2 {
+            return points.to_vec();
+        }
+        let segments = points.len() - 1;
+        match self {
+            SplineCurve::CatmullRom => {
+                // the end points are mirrored, so the curve leaves and enters them in the direction of the neighbours
+                let first = 2. * points[0] - points[1];
+                let last = 2. * points[segments] - points[segments - 1];
+                let point = |i: isize| match i {
+                    -1 => first,
+                    i if i as usize > segments => last,
+                    i => points[i as usize],
+                };
+                let mut polyline = vec![points[0]];
+                for segment in 0..segments as isize {
+                    let (p0, p1, p2, p3) = (
+                        point(segment - 1),
+                        point(segment),
+                        point(segment + 1),
+                        point(segment + 2),
+                    );
+                    for sample in 1..=SAMPLES_PER_SEGMENT {
+                        let t = sample as f32 / SAMPLES_PER_SEGMENT as f32;
+                        polyline.push(
+                            0.5 * (2. * p1
+                                + (p2 - p0) * t
+                                + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t * t
+                                + (3. * p1 - p0 - 3. * p2 + p3) * t * t * t),
+                        );
+                    }
+                }
+                polyline
+            }
+            SplineCurve::Bezier => (0..=segments * SAMPLES_PER_SEGMENT)
+                .map(|sample| {
+                    // de Casteljau
+                    let t = sample as f32 / (segments * SAMPLES_PER_SEGMENT) as f32;
+                    let mut points = points.to_vec();
+                    while points.len() > 1 {
+                        points = points.windows(2).map(|w| w[0].lerp(w[1], t)).collect();
+                    }
+                    points[0]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Places joints along a polyline, starting at its first point, so consecutive joints are `lengths` apart.
+/// Beyond the end of the polyline, the joints continue in the direction of its last segment.
+pub fn fit_joints(polyline: &[Vec3], lengths: &[f32]) -> Vec<Vec3> {
+    let mut joints = Vec::with_capacity(lengths.len() + 1);
+    let mut joint = match polyline.first() {
+        Some(first) => *first,
+        None => return joints,
+    };
+    joints.push(joint);
+
+    // the segment the last joint lies on, and the position of the joint on it
+    let mut segment = 0;
+    let mut pos = joint;
+    for length in lengths {
+        loop {
+            if segment + 1 >= polyline.len() {
+                // beyond the end of the curve
+                let dir = match polyline.len() {
+                    0 | 1 => Vec3::Y,
+                    len => (polyline[len - 1] - polyline[len - 2]).normalize_or_zero(),
+                };
+                joint += dir * *length;
+                pos = joint;
+                break;
+            }
+            let end = polyline[segment + 1];
+            if end.distance(joint) < *length {
+                segment += 1;
+                pos = end;
+                continue;
+            }
+            // the segment leaves the sphere around the joint, intersect them
+            let dir = end - pos;
+            let to_pos = pos - joint;
+            let (a, b, c) = (
+                dir.length_squared(),
+                to_pos.dot(dir),
+                to_pos.length_squared() - length * length,
+            );
+            let t = match a > f32::EPSILON {
+                true => ((-b + (b * b - a * c).max(0.).sqrt()) / a).clamp(0., 1.),
+                false => 1.,
+            };
+            pos += dir * t;
+            joint = pos;
+            break;
+        }
+        joints.push(joint);
+    }
+    joints
+}
+
+pub fn apply_spline_ik(
+    mut splines: Query<&mut SplineIk>,
+    mut bones: Query<&mut Transform, With<Bone>>,
+    global_tfs: Query<&GlobalTransform>,
+    parents: Query<&Parent>,
+    children: Query<&Children>,
+    rest_poses: Query<&RestPose>,
+    settings: Res<IkSettings>,
+) {
+    for mut spline in splines.iter_mut() {
+        if !bones.contains(spline.tip_bone) {
+            continue;
+        }
+
+        // the chain from its base to the tip bone
+        let mut chain = vec![spline.tip_bone];
+        while chain.len() < spline.chain_length as usize {
+            match parents.get(*chain.last().unwrap()) {
+                Ok(parent) if bones.contains(parent.get()) => chain.push(parent.get()),
+                _ => break,
+            }
+        }
+        chain.reverse();
+
+        // the tip bone can only be aimed if it has a child bone
+        let tip_child = children
+            .get(spline.tip_bone)
+            .into_iter()
+            .flatten()
+            .find(|child_id| bones.contains(**child_id))
+            .copied();
+
+        let joints: Vec<Vec3> = match chain
+            .iter()
+            .chain(tip_child.iter())
+            .map(|bone_id| global_tfs.get(*bone_id).map(|gt| gt.translation()))
+            .collect()
+        {
+            Ok(joints) => joints,
+            Err(_) => continue,
+        };
+        let lengths: Vec<f32> = joints.windows(2).map(|w| w[0].distance(w[1])).collect();
+
+        let controls: Vec<&GlobalTransform> = spline
+            .controls
+            .iter()
+            .filter_map(|control| global_tfs.get(*control).ok())
+            .collect();
+        if controls.is_empty() {
+            continue;
+        }
+        let points: Vec<Vec3> = [joints[0]]
+            .into_iter()
+            .chain(controls.iter().map(|control| control.translation()))
+            .collect();
+        let fitted = fit_joints(&spline.curve.sample(&points), &lengths);
+
+        // the solved transforms are not propagated yet, walk down from the parent of the chain
+        let mut par_tf_global = parents
+            .get(chain[0])
+            .ok()
+            .and_then(|parent| global_tfs.get(parent.get()).ok())
+            .copied()
+            .unwrap_or(GlobalTransform::IDENTITY);
+
+        let par_rot = par_tf_global.to_scale_rotation_translation().1;
+        let control_rot = controls.last().unwrap().to_scale_rotation_translation().1;
+        let twist = twist_angle(par_rot.inverse() * control_rot, spline.twist_axis);
+        // each bone takes the share of its length, so the twist is spread evenly along the curve
+        let total_length: f32 = lengths.iter().sum();
+        let twist_share = |i: usize| match total_length > 0. {
+            true => lengths.get(i).copied().unwrap_or(0.) / total_length,
+            false => 1. / chain.len() as f32,
+        };
+
+        let mut last_rotations = HashMap::new();
+        for (i, bone_id) in chain.iter().enumerate() {
+            let child_translation = match chain.get(i + 1).or(tip_child.as_ref()) {
+                Some(child_id) => bones
+                    .get(*child_id)
+                    .ok()
+                    .map(|child_tf| child_tf.translation),
+                None => None,
+            };
+            let mut bone_tf = bones.get_mut(*bone_id).unwrap();
+
+            // undo the last frame, unless somebody else (e.g. an animation) has rotated the bone since.
+            // Otherwise the animated reference would contain the twist of the last frame, which would add up.
+            if let Some((original, applied)) = spline.last_rotations.get(bone_id) {
+                if *applied == bone_tf.rotation {
+                    bone_tf.rotation = *original;
+                }
+            }
+            let original = bone_tf.rotation;
+
+            // only swing the reference rotation, so the bone keeps the reference twist
+            let ref_rot = match settings.twist_source {
+                TwistSource::Rest => rest_poses
+                    .iter()
+                    .find_map(|rest_pose| rest_pose.transforms.get(bone_id))
+                    .map_or(bone_tf.rotation, |rest_tf| rest_tf.rotation),
+                TwistSource::Animated => bone_tf.rotation,
+            };
+            let (rotation, axis) = match (child_translation, fitted.get(i + 1)) {
+                (Some(child_translation), Some(target)) => (
+                    aim_bone(
+                        &par_tf_global,
+                        &bone_tf,
+                        ref_rot,
+                        child_translation,
+                        *target,
+                    ),
+                    child_translation,
+                ),
+                _ => (ref_rot, spline.twist_axis),
+            };
+
+            // each bone adds its share of the twist around its own axis, which doesn't move its child joint
+            let bone_twist = match axis.try_normalize() {
+                Some(axis) => Quat::from_axis_angle(axis, twist * twist_share(i)),
+                None => Quat::IDENTITY,
+            };
+            bone_tf.rotation = (rotation * bone_twist).normalize();
+            last_rotations.insert(*bone_id, (original, bone_tf.rotation));
+            par_tf_global = par_tf_global.mul_transform(*bone_tf);
+        }
+        spline.last_rotations = last_rotations;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f32 = 0.001;
+
+    fn arc() -> Vec<Vec3> {
+        vec![Vec3::ZERO, Vec3::new(1., 1., 0.), Vec3::new(2., 0., 0.)]
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_controls() {
+        let polyline = SplineCurve::CatmullRom.sample(&arc());
+        for point in arc() {
+            assert!(polyline.iter().any(|p| p.distance(point) < TOLERANCE));
+        }
+        assert_eq!(polyline.len(), 2 * SAMPLES_PER_SEGMENT + 1);
+    }
+
+    #[test]
+    fn bezier_passes_through_end_points_only() {
+        let polyline = SplineCurve::Bezier.sample(&arc());
+        assert_eq!(polyline[0], Vec3::ZERO);
+        assert!(polyline.last().unwrap().distance(Vec3::X * 2.) < TOLERANCE);
+        // the middle of a quadratic Bezier curve is halfway to its middle control point
+        let middle = polyline[polyline.len() / 2];
+        assert!(middle.distance(Vec3::new(1., 0.5, 0.)) < TOLERANCE);
+    }
+
+    #[test]
+    fn fitted_joints_keep_lengths() {
+        let polyline = SplineCurve::CatmullRom.sample(&arc());
+        let lengths = [0.5, 0.3, 0.4, 0.2, 2.];
+        let joints = fit_joints(&polyline, &lengths);
+
+        assert_eq!(joints.len(), lengths.len() + 1);
+        for (w, length) in joints.windows(2).zip(lengths) {
+            assert!((w[0].distance(w[1]) - length).abs() < TOLERANCE);
+        }
+        // all but the last joint lie on the curve
+        for joint in &joints[..lengths.len()] {
+            let closest = polyline
+                .iter()
+                .map(|p| p.distance(*joint))
+                .fold(f32::MAX, f32::min);
+            assert!(closest < 0.1);
+        }
+    }
+}
@@ -1,9 +1,151 @@
-use crate::components::{ArmatureGraph, Bone, IkData, IkGoal, IkSettings};
+use crate::{
+    components::{
+        ArmatureBones, ArmatureGraph, Bone, BoneIndex, BoneMass, BoneRef, BoneStiffness, IkData,
+        IkGoal, IkGoalAnchor, IkGoalLink, IkGoalTarget, IkSettings, IkSmoothing, Planted, RestPose,
+        RootMotion, TwistHelper, TwistSource,
+    },
+    solver::{JointGoal, Skeleton},
+};
 use bevy::{
+    ecs::system::SystemState,
+    math::Affine3A,
     prelude::*,
     utils::{HashMap, HashSet},
 };
-use std::collections::VecDeque;
+use std::{collections::VecDeque, f32::consts::PI};
+
+pub fn index_bones(
+    bones: Query<(Entity, &Bone, Option<&Children>, Option<&Parent>)>,
+    mut index: ResMut<BoneIndex>,
+    mut warned: Local<HashSet<(Entity, String)>>,
+) {
+    index.armatures.clear();
+
+    // armature roots are bones without a parent bone
+    for (root_id, root_bone, _, parent) in bones.iter() {
+        if parent.is_some_and(|parent| bones.contains(parent.get())) {
+            continue;
+        }
+
+        // walk down the bone tree, building the paths on the way
+        let mut armature = ArmatureBones::default();
+        let mut duplicates = HashSet::<BoneRef>::new();
+        let mut todo_stack = vec![(root_id, root_bone.name.clone())];
+        while let Some((bone_id, path)) = todo_stack.pop() {
+            let (_, bone, children, _) = bones.get(bone_id).unwrap();
+            armature.bones.push(bone_id);
+            if armature
+                .by_name
+                .insert(bone.name.clone(), bone_id)
+                .is_some()
+            {
+                duplicates.insert(BoneRef::Name(bone.name.clone()));
+            }
+            if armature.by_path.insert(path.clone(), bone_id).is_some() {
+                duplicates.insert(BoneRef::Path(path.clone()));
+            }
+
+            for &child_id in children.into_iter().flatten() {
+                if let Ok((_, child_bone, _, _)) = bones.get(child_id) {
+                    todo_stack.push((child_id, format!("{}/{}", path, child_bone.name)));
+                }
+            }
+        }
+
+        // names and paths of multiple bones are ambiguous, leave them out instead of picking any of the bones
+        for duplicate in duplicates {
+            let (map, name) = match &duplicate {
+                BoneRef::Name(name) => (&mut armature.by_name, name),
+                BoneRef::Path(path) => (&mut armature.by_path, path),
+            };
+            map.remove(name);
+            if warned.insert((root_id, name.clone())) {
+                warn!(
+                    "Multiple bones of the armature {:?} are named {:?}, they can't be looked up by it",
+                    root_id, duplicate
+                );
+            }
+        }
+        index.armatures.insert(root_id, armature);
+    }
+}
+
+/// An exclusive system, so the rest poses are inserted right away and the first solve of an armature uses them.
+#[allow(clippy::type_complexity)]
+pub fn capture_rest_poses(
+    world: &mut World,
+    state: &mut SystemState<(
+        Query<(Entity, Option<&Parent>), (With<Bone>, Without<RestPose>)>,
+        Query<(), With<Bone>>,
+        Query<(&Transform, Option<&Children>), With<Bone>>,
+    )>,
+) {
+    // armatures without a rest pose get their current pose as rest pose, before it is modified by the solver
+    let (new_bones, bones, bone_tfs) = state.get(world);
+    let rest_poses: Vec<(Entity, RestPose)> = new_bones
+        .iter()
+        .filter(|(_, parent)| !parent.is_some_and(|parent| bones.contains(parent.get())))
+        .map(|(root_id, _)| (root_id, RestPose::capture(root_id, &bone_tfs)))
+        .collect();
+    for (root_id, rest_pose) in rest_poses {
+        world.entity_mut(root_id).insert(rest_pose);
+    }
+}
+
+pub fn resolve_goal_targets(
+    mut commands: Commands,
+    targets: Query<(
+        Entity,
+        &IkGoalTarget,
+        ChangeTrackers<IkGoalTarget>,
+        Option<&IkGoal>,
+    )>,
+    bones: Query<(), With<Bone>>,
+    parents: Query<&Parent>,
+    index: Res<BoneIndex>,
+) {
+    for (goal_id, target, tracker, goal) in targets.iter() {
+        // resolved goals are only resolved again if their target changed or their bone is gone
+        if goal.is_some_and(|goal| bones.contains(goal.target_bone)) && !tracker.is_changed() {
+            continue;
+        }
+
+        let bone_id = match target.armature {
+            Some(scope_id) => index
+                .armatures
+                .iter()
+                .filter(|(&root_id, _)| {
+                    // the scope is either the root bone itself or one of its ancestors
+                    let mut cur_id = root_id;
+                    loop {
+                        if cur_id == scope_id {
+                            break true;
+                        }
+                        match parents.get(cur_id) {
+                            Ok(parent) => cur_id = parent.get(),
+                            Err(_) => break false,
+                        }
+                    }
+                })
+                .find_map(|(_, bones)| bones.get(&target.bone)),
+            None => index.find(&target.bone),
+        };
+
+        // the bone might not be spawned yet, try again next frame
+        match bone_id {
+            Some(bone_id) => {
+                commands.entity(goal_id).insert(IkGoal {
+                    target_bone: bone_id,
+                    chain_length: target.chain_length,
+                });
+            }
+            None if goal.is_some() => {
+                commands.entity(goal_id).remove::<IkGoal>();
+            }
+            None => {}
+        }
+    }
+}
 
 pub fn create_armature_tree(
     bone_parents: Query<(Entity, &Children), With<Bone>>,
@@ -51,9 +193,10 @@ pub fn create_armature_tree(
             }
         }
 
-        // if this bone is a root bone, add a root joint
+        // if this bone is a root bone, add a root joint. Each armature has its own root joint
         if !is_out_bone {
             graph.out_bones.entry(joint_id).or_default().insert(bone_id);
+            joint_id += 1;
         }
     }
 
@@ -92,17 +235,24 @@ pub fn create_armature_tree(
     graph.joint_parent = joint_parent;
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn cache_ik_data(
     bones: Query<(Entity, &Bone, &Transform, &GlobalTransform), With<Bone>>,
     goals: Query<(Entity, &GlobalTransform, &IkGoal), Without<Bone>>,
+    rest_poses: Query<&RestPose>,
+    bone_options: Query<(
+        Option<&BoneMass>,
+        Option<&RootMotion>,
+        Option<&BoneStiffness>,
+    )>,
+    global_tfs: Query<(&GlobalTransform, Option<&Parent>)>,
     graph: Res<ArmatureGraph>,
+    settings: Res<IkSettings>,
     mut data: ResMut<IkData>,
 ) {
     // clear the data
     data.joint_positions.clear();
-    data.required_positions.clear();
     data.joints_to_goals.clear();
-    data.roots.clear();
     data.bone_lengths.clear();
 
     // register joint to goal mapping
@@ -113,30 +263,6 @@ pub fn cache_ik_data(
         }
     }
 
-    // register roots and required positions
-    for (_, _, goal) in goals.iter() {
-        let mut cur_id = graph.base_joint.get(&goal.target_bone).unwrap();
-        for i in 0..goal.chain_length {
-            if let Some(par_id) = graph.joint_parent.get(cur_id) {
-                // add the child bone as a required bone for the parent bone
-                data.required_positions
-                    .entry(*par_id)
-                    .or_default()
-                    .insert(*cur_id);
-                cur_id = par_id;
-            } else {
-                // bone without parent, this is the root
-                data.roots.insert(*cur_id);
-                break;
-            }
-
-            //if we stop going up the tree due to chain length limitation, this node now also counts as a pseudo-root
-            if i == goal.chain_length - 1 {
-                data.roots.insert(*cur_id);
-            }
-        }
-    }
-
     // initialize positions
     for (bone_id, _, _, gt) in bones.iter() {
         if let Some(base_joint) = graph.base_joint.get(&bone_id) {
@@ -154,158 +280,239 @@ pub fn cache_ik_data(
             data.bone_lengths.insert(bone_id, dist);
         }
     }
+
+    // use the rest lengths where available, so bone lengths don't drift with the animation
+    for rest_pose in rest_poses.iter() {
+        for (bone_id, rest_length) in rest_pose.bone_lengths.iter() {
+            // the rest lengths live in the parent space, convert them to world units in the current bone direction
+            let (base_joint, pole_joint, (_, _, bone_tf, bone_gt)) = match (
+                graph.base_joint.get(bone_id),
+                graph.pole_joint.get(bone_id),
+                bones.get(*bone_id),
+            ) {
+                (Some(base_joint), Some(pole_joint), Ok(bone)) => (base_joint, pole_joint, bone),
+                _ => continue,
+            };
+            let dir = *data.joint_positions.get(pole_joint).unwrap()
+                - *data.joint_positions.get(base_joint).unwrap();
+            let length = world_bone_length(bone_gt, bone_tf, *rest_length, dir);
+            data.bone_lengths.insert(*bone_id, length);
+        }
+    }
+
+    // the joint tree for the solver, with the length of the bone ending at each joint
+    let skeleton = Skeleton {
+        joint_parent: graph.joint_parent.clone(),
+        bone_lengths: graph
+            .in_bone
+            .iter()
+            .filter_map(|(joint_id, bone_id)| Some((*joint_id, *data.bone_lengths.get(bone_id)?)))
+            .collect(),
+        masses: graph
+            .in_bone
+            .iter()
+            .filter_map(|(joint_id, bone_id)| {
+                Some((*joint_id, bone_options.get(*bone_id).ok()?.0?.0))
+            })
+            .collect(),
+        max_root_offsets: graph
+            .base_joint
+            .iter()
+            .filter_map(|(bone_id, joint_id)| {
+                Some((*joint_id, bone_options.get(*bone_id).ok()?.1?.max_offset))
+            })
+            .collect(),
+        stiffness: graph
+            .in_bone
+            .iter()
+            .filter_map(|(joint_id, bone_id)| {
+                Some((*joint_id, bone_options.get(*bone_id).ok()?.2?.0))
+            })
+            .collect(),
+    };
+    data.chains = skeleton.chains(goals.iter().filter_map(|(_, _, goal)| {
+        let base_joint = graph.base_joint.get(&goal.target_bone)?;
+        Some((*base_joint, settings.chain_length(goal.chain_length)))
+    }));
+    data.skeleton = skeleton;
+
+    // warm start - seed the chain joints (except roots) with the last solution, which moves along with the armature
+    if settings.warm_start {
+        for (bone_id, _, _, _) in bones.iter() {
+            let base_joint = match graph.base_joint.get(&bone_id) {
+                Some(base_joint) if data.chains.contains(*base_joint) => *base_joint,
+                _ => continue,
+            };
+            if let Some((root_id, local_pos)) = data.last_positions.get(&bone_id) {
+                if let Some(space) = warm_start_space(*root_id, &global_tfs) {
+                    let pos = space.transform_point3(*local_pos);
+                    data.joint_positions.insert(base_joint, pos);
+                }
+            }
+        }
+    }
+}
+
+pub fn resolve_goal_positions(
+    goals: Query<(Entity, &GlobalTransform, Option<&IkGoalAnchor>), With<IkGoal>>,
+    anchors: Query<&GlobalTransform>,
+    mut data: ResMut<IkData>,
+) {
+    data.goal_positions.clear();
+
+    for (goal_id, goal_tf, anchor) in goals.iter() {
+        // anchored goals follow their anchor entity, if it still exists. Otherwise use the goal's own transform
+        let anchor_tf = anchor.and_then(|anchor| {
+            anchors
+                .get(anchor.entity)
+                .ok()
+                .map(|anchor_tf| anchor_tf.mul_transform(anchor.offset))
+        });
+        let pos = anchor_tf.unwrap_or(*goal_tf).translation();
+        data.goal_positions.insert(goal_id, pos);
+    }
 }
 
 pub fn compute_joint_positions(
-    goals: Query<(&GlobalTransform, &IkGoal)>,
+    mut goals: Query<(Entity, &IkGoal, Option<&Planted>, Option<&mut IkGoalLink>)>,
+    global_tfs: Query<(&GlobalTransform, Option<&Parent>)>,
+    index: Res<BoneIndex>,
     graph: Res<ArmatureGraph>,
     settings: Res<IkSettings>,
     mut data: ResMut<IkData>,
 ) {
-    // queue to walk through the armature graph
-    let mut todo_queue = VecDeque::<u32>::new();
-
-    for _ in 0..settings.max_iterations {
-        // check if target bones are close enough to the goals
-        let mut highest_dist: f32 = 0.0;
-        for (goal_tf, goal) in goals.iter() {
-            let goal_joint = graph.base_joint.get(&goal.target_bone).unwrap();
-            let pos = data.joint_positions.get(goal_joint).unwrap();
-            let dist = (goal_tf.translation() - *pos).length();
-            highest_dist = highest_dist.max(dist);
-        }
-        if highest_dist < settings.goal_tolerance {
-            break;
-        }
-        /*
-         * FORWARD PASS - LEAF TO ROOT
-         */
-
-        // initialize todo queue with starting joints (joints with goals)
-        todo_queue.clear();
-        for (_, goal) in goals.iter() {
-            let goal_joint = graph.base_joint.get(&goal.target_bone).unwrap();
-            todo_queue.push_back(*goal_joint);
+    let data = &mut *data;
+    // goals whose bone is gone are skipped until they are removed or resolved again
+    let joint_goals: Vec<JointGoal> = goals
+        .iter()
+        .filter_map(|(goal_id, goal, planted, link)| {
+            Some(JointGoal {
+                joint: *graph.base_joint.get(&goal.target_bone)?,
+                chain_length: goal.chain_length,
+                position: *data.goal_positions.get(&goal_id)?,
+                planted: planted.is_some(),
+                linked: link
+                    .and_then(|link| goals.get(link.other).ok())
+                    .and_then(|(_, other, _, _)| graph.base_joint.get(&other.target_bone))
+                    .copied(),
+            })
+        })
+        .collect();
+    data.skeleton.solve_chains(
+        &data.chains,
+        &mut data.joint_positions,
+        &joint_goals,
+        &settings,
+    );
+
+    // report how far the linked goals are off. Goals without a global transform have no position
+    for (goal_id, goal, _, link) in goals.iter_mut() {
+        let joint_pos = graph
+            .base_joint
+            .get(&goal.target_bone)
+            .and_then(|joint| data.joint_positions.get(joint));
+        if let (Some(mut link), Some(joint_pos), Some(goal_pos)) =
+            (link, joint_pos, data.goal_positions.get(&goal_id))
+        {
+            link.error = *joint_pos - *goal_pos;
         }
+    }
 
-        // new positions
-        let mut new_positions = HashMap::<u32, Vec3>::new();
-
-        // actual forward pass
-        while let Some(joint_id) = todo_queue.pop_front() {
-            // check if all required joint children have a new position computed, otherwise push this joint back into the queue
-            let mut ready = true;
-            if let Some(reqs) = data.required_positions.get(&joint_id) {
-                for req_id in reqs {
-                    if !new_positions.contains_key(req_id) {
-                        ready = false;
-                        break;
-                    }
-                }
-            }
-            if !ready {
-                todo_queue.push_back(joint_id);
+    // remember the solution relative to the armature root bones for warm starting
+    let mut last_positions = HashMap::<Entity, (Entity, Vec3)>::new();
+    for (&root_id, armature) in index.armatures.iter() {
+        let root_inv = match warm_start_space(root_id, &global_tfs) {
+            Some(space) => space.inverse(),
+            None => continue,
+        };
+        for &bone_id in armature.bones.iter() {
+            let base_joint = graph.base_joint.get(&bone_id).unwrap();
+            // only joints which are part of a chain have been solved
+            if !data.chains.required_positions.contains_key(base_joint)
+                && !data.joints_to_goals.contains_key(base_joint)
+            {
                 continue;
             }
-
-            // figure out the new forward position for this joint
-            if let Some(goal_id) = data.joints_to_goals.get(&joint_id) {
-                // in the forward pass, the target bone of the goal is simply set to the goal position
-                let (goal_transform, _) = goals.get(*goal_id).unwrap();
-                new_positions.insert(joint_id, goal_transform.translation());
-            } else {
-                // otherwise compute a new position for each child
-                // the new position is the centroid of those positions
-                let old_pos = data.joint_positions.get(&joint_id).unwrap();
-                let children = data.required_positions.get(&joint_id).unwrap();
-                let mut new_pos_centroid = Vec3::ZERO;
-                for child_id in children {
-                    let bone_id = graph.in_bone.get(child_id).unwrap();
-                    let child_link_length = data.bone_lengths.get(bone_id).unwrap();
-
-                    let new_child_pos = new_positions.get(child_id).unwrap();
-                    let dir = (*old_pos - *new_child_pos).normalize() * *child_link_length;
-                    let new_pos = *new_child_pos + dir;
-                    new_pos_centroid += new_pos;
-                }
-                new_pos_centroid *= 1. / children.len() as f32;
-                new_positions.insert(joint_id, new_pos_centroid);
-            }
-
-            // if we are not at the root, push the parent to the todo_queue, if it's not already in there
-            if !data.roots.contains(&joint_id) {
-                if let Some(par_id) = graph.joint_parent.get(&joint_id) {
-                    if !todo_queue.contains(par_id) {
-                        todo_queue.push_back(*par_id);
-                    }
-                }
-            }
-        }
-
-        /*
-         * BACKWARD PASS - ROOT TO LEAF
-         */
-
-        // prepare todo queue for backward pass
-        todo_queue.clear();
-        for root in data.roots.iter() {
-            todo_queue.push_back(*root);
+            let pos = data.joint_positions.get(base_joint).unwrap();
+            last_positions.insert(bone_id, (root_id, root_inv.transform_point3(*pos)));
         }
+    }
+    data.last_positions = last_positions;
+}
 
-        // actual backward pass
-        while let Some(joint_id) = todo_queue.pop_front() {
-            // if this bone is one of the roots or pseudo-roots, we just set them back to their original position
-            if data.roots.contains(&joint_id) {
-                let old_pos = data.joint_positions.get(&joint_id).unwrap();
-                new_positions.insert(joint_id, *old_pos);
-            } else {
-                let in_bone_id = graph.in_bone.get(&joint_id).unwrap();
-                let bone_length = data.bone_lengths.get(in_bone_id).unwrap();
-                let par_id = graph.joint_parent.get(&joint_id).unwrap();
-                let par_pos = new_positions.get(par_id).unwrap();
-                let forward_pos = new_positions.get(&joint_id).unwrap();
-                let dir = (*forward_pos - *par_pos).normalize() * *bone_length;
-                let backward_pos = *par_pos + dir;
-                new_positions.insert(joint_id, backward_pos);
+/// Moves the root bones with a [`RootMotion`] to their solved positions, and reports the offset.
+/// This is the only system writing their translation, so the translation recorded for [`reset_root_motion`]
+/// stays exact.
+pub fn apply_root_motion(
+    mut roots: Query<(Entity, &mut RootMotion, &mut Transform, &GlobalTransform), With<Bone>>,
+    parents: Query<&Parent>,
+    global_tfs: Query<&GlobalTransform>,
+    graph: Res<ArmatureGraph>,
+    data: Res<IkData>,
+) {
+    for (bone_id, mut root_motion, mut bone_tf, bone_gt) in roots.iter_mut() {
+        let new_pos = match graph
+            .base_joint
+            .get(&bone_id)
+            .and_then(|base_joint| data.joint_positions.get(base_joint))
+        {
+            Some(new_pos) => *new_pos,
+            None => {
+                root_motion.offset = Vec3::ZERO;
+                continue;
             }
+        };
+
+        // the global transform is not updated yet, so it still contains the position before solving.
+        // The solved position is global, move it into the space of the parent
+        let original = bone_tf.translation;
+        bone_tf.translation = match parents
+            .get(bone_id)
+            .map(|parent| global_tfs.get(parent.get()))
+        {
+            Ok(Ok(par_gt)) => par_gt.affine().inverse().transform_point3(new_pos),
+            _ => new_pos,
+        };
+        root_motion.offset = new_pos - bone_gt.translation();
+        root_motion.last_translation = Some((original, bone_tf.translation));
+    }
+}
 
-            // put all required children in the todo queue - those who lead to a leaf bone with IK goal
-            if let Some(children) = data.required_positions.get(&joint_id) {
-                for child_id in children {
-                    todo_queue.push_back(*child_id);
-                }
+/// Moves the root bones with a [`RootMotion`] back to their position before the last solve, unless somebody else
+/// (e.g. an animation) has moved them since.
+pub fn reset_root_motion(mut roots: Query<(&mut RootMotion, &mut Transform)>) {
+    for (mut root_motion, mut bone_tf) in roots.iter_mut() {
+        if let Some((original, applied)) = root_motion.last_translation.take() {
+            if applied == bone_tf.translation {
+                bone_tf.translation = original;
             }
         }
-
-        // "flip the buffer"
-        data.joint_positions = new_positions;
     }
 }
 
-const EPS: f32 = 0.01;
+#[allow(clippy::too_many_arguments)]
 pub fn apply_bone_rotations(
     mut bones: Query<(Entity, &mut Transform), With<Bone>>,
+    root_motions: Query<(), With<RootMotion>>,
     parents: Query<&Parent>,
     global_tfs: Query<&GlobalTransform>,
+    rest_poses: Query<&RestPose>,
     graph: Res<ArmatureGraph>,
+    settings: Res<IkSettings>,
     data: Res<IkData>,
 ) {
-    println!("###################");
-    println!("OUT BONES {:?}", graph.out_bones);
-    println!("IN BONE {:?}", graph.in_bone);
-    println!("POS {:?}", data.joint_positions);
-    println!("###################");
     // queue to walk through the armature graph
     let mut todo_queue = VecDeque::<Entity>::new();
 
     // updated - global transforms
     let mut par_tfs_global = HashMap::<Entity, GlobalTransform>::new();
 
-    // enqueue bones connected to a root joint
+    // enqueue chain bones connected to a root joint
     for (bone_id, _) in bones.iter() {
         let base_joint = graph.base_joint.get(&bone_id).unwrap();
         // check if this bone is associated to a root joint
-        if data.roots.contains(base_joint) {
+        // only bones between two joints of a chain are rotated, all other bones keep their local transform
+        if data.chains.roots.contains(base_joint) && is_chain_bone(&graph, &data, bone_id) {
             // enqueue the bone
             todo_queue.push_back(bone_id);
             // register the global transform of the parent, if no parent exists, register identity transform
@@ -320,66 +527,355 @@ pub fn apply_bone_rotations(
 
     // apply position changes by rotation only - from root to children
     while let Some(bone_id) = todo_queue.pop_front() {
-        println!("+++ {:?} +++", bone_id);
-        let base_tf_local = *bones.get_mut(bone_id).unwrap().1;
-        let par_tf_global = par_tfs_global.get(&bone_id).unwrap();
-        let base_tf_global = par_tf_global.mul_transform(base_tf_local);
+        let par_tf_global = *par_tfs_global.get(&bone_id).unwrap();
+        let pole_joint = graph.pole_joint.get(&bone_id).unwrap();
+        let new_pole_pos_global = *data.joint_positions.get(pole_joint).unwrap();
+
+        // ASSUMPTION: ALL CHILD BONES HAVE THE SAME LOCAL TRANSLATION
+        let pole_tf_local = *graph
+            .out_bones
+            .get(pole_joint)
+            .unwrap() // if the bone has a pole_joint, it has to have child bones
+            .iter()
+            .map(|&bid| bones.get(bid).unwrap().1)
+            .next()
+            .unwrap(); // if the bone has child_bones, it has to have at least one
+
+        // movable armature roots have been moved by the solver as well, roots with a root motion already
+        // by apply_root_motion
+        let mut base_tf_local = bones.get_mut(bone_id).unwrap().1;
         let base_joint = graph.base_joint.get(&bone_id).unwrap();
-        let base_pos_global = *data.joint_positions.get(base_joint).unwrap();
+        if data.skeleton.is_movable_root(*base_joint, &settings) && !root_motions.contains(bone_id)
+        {
+            let new_base_pos_global = *data.joint_positions.get(base_joint).unwrap();
+            base_tf_local.translation = par_tf_global
+                .affine()
+                .inverse()
+                .transform_point3(new_base_pos_global);
+        }
 
-        // check that the base is already at the correct position
-        // (this should be the same as the pole check of the previous iteration)
-        assert!(base_tf_global.translation().distance(base_pos_global) < EPS);
+        // only swing the reference rotation, so the bone keeps the reference twist
+        let ref_rot = match settings.twist_source {
+            TwistSource::Rest => rest_poses
+                .iter()
+                .find_map(|rest_pose| rest_pose.transforms.get(&bone_id))
+                .map_or(base_tf_local.rotation, |rest_tf| rest_tf.rotation),
+            TwistSource::Animated => base_tf_local.rotation,
+        };
+
+        // apply the rotation
+        base_tf_local.rotation = aim_bone(
+            &par_tf_global,
+            &base_tf_local,
+            ref_rot,
+            pole_tf_local.translation,
+            new_pole_pos_global,
+        );
+
+        // update global base transform
+        let base_tf_global = par_tf_global.mul_transform(*base_tf_local);
+
+        // register new global tf for all chain children and add them to the queue
+        for child_bone in graph.out_bones.get(pole_joint).unwrap() {
+            if is_chain_bone(&graph, &data, *child_bone) {
+                todo_queue.push_back(*child_bone);
+                par_tfs_global.insert(*child_bone, base_tf_global);
+            }
+        }
+    }
+}
 
-        if let Some(pole_joint) = graph.pole_joint.get(&bone_id) {
-            if let Some(&new_pole_pos_global) = data.joint_positions.get(pole_joint) {
-                // ASSUMPTION: ALL CHILD BONES HAVE THE SAME LOCAL TRANSLATION
-                let pole_tf_local = *graph
-                    .out_bones
-                    .get(pole_joint)
-                    .unwrap() // if the bone has a pole_joint, it has to have child bones
-                    .iter()
-                    .map(|&bid| bones.get(bid).unwrap().1)
-                    .next()
-                    .unwrap(); // if the bone has child_bones, it has to have at least one
-
-                // generate rotation
-                let par_rot = par_tf_global.compute_transform().rotation.normalize();
-                let global_rot = base_tf_global.compute_transform().rotation.normalize();
-                let local_rot = base_tf_local.rotation.normalize();
-                let old_dir = pole_tf_local.translation;
-                let new_dir_global = new_pole_pos_global - base_pos_global;
-                let new_dir = global_rot.mul_vec3(new_dir_global);
-
-                let new_rot =
-                    Quat::from_rotation_arc(old_dir.normalize(), new_dir_global.normalize());
-
-                println!("GLOBAL ROT {}", global_rot);
-                println!("ROTATE FROM {} TO {}", old_dir, new_dir);
-
-                // apply the rotation
-                let mut base_tf_local = bones.get_mut(bone_id).unwrap().1;
-                base_tf_local.rotate(new_rot);
-
-                // update global base transform
-                let base_tf_global = par_tf_global.mul_transform(*base_tf_local);
-
-                // compute global pole transform
-                let pole_tf_global = base_tf_global.mul_transform(pole_tf_local);
-
-                println!("POLE GOAL {}", new_pole_pos_global);
-                println!("POLE ACTL {}", pole_tf_global.translation());
-
-                // check that the updated global base and pole are at the correct position
-                assert!(base_tf_global.translation().distance(base_pos_global) < EPS);
-                assert!(pole_tf_global.translation().distance(new_pole_pos_global) < EPS);
-
-                // register new global tf for all bone children and add them to the queue
-                for child_bone in graph.out_bones.get(pole_joint).unwrap() {
-                    todo_queue.push_back(*child_bone);
-                    par_tfs_global.insert(*child_bone, base_tf_global);
+/// Converts a bone length in the space of the parent bone into world units, for a bone pointing in the
+/// global direction `dir`. With a non-uniformly scaled parent, the world length depends on the direction.
+pub fn world_bone_length(
+    bone_tf_global: &GlobalTransform,
+    bone_tf_local: &Transform,
+    length: f32,
+    dir: Vec3,
+) -> f32 {
+    // the parent transform is the global transform without the local transform of the bone
+    let par_affine = bone_tf_global.affine() * bone_tf_local.compute_affine().inverse();
+    let dir_local = par_affine
+        .inverse()
+        .transform_vector3(dir.normalize_or_zero());
+    if dir_local.length_squared() < f32::EPSILON {
+        return length * par_affine.matrix3.determinant().abs().cbrt();
+    }
+    length / dir_local.length()
+}
+
+/// The space the last solution of an armature is kept in for warm starting. It moves along with the root bone
+/// and turns with the parent of the root bone, but not with the animated rotation of the root bone, which
+/// might be part of a chain itself.
+fn warm_start_space(
+    root_id: Entity,
+    global_tfs: &Query<(&GlobalTransform, Option<&Parent>)>,
+) -> Option<Affine3A> {
+    let (root_gt, parent) = global_tfs.get(root_id).ok()?;
+    let (scale, rotation, _) = parent
+        .and_then(|parent| global_tfs.get(parent.get()).ok())
+        .map_or(GlobalTransform::IDENTITY, |(par_gt, _)| *par_gt)
+        .to_scale_rotation_translation();
+    Some(Affine3A::from_scale_rotation_translation(
+        scale,
+        rotation,
+        root_gt.translation(),
+    ))
+}
+
+/// A bone is part of a chain if both its base and pole joint are solved.
+fn is_chain_bone(graph: &ArmatureGraph, data: &IkData, bone_id: Entity) -> bool {
+    match (
+        graph.base_joint.get(&bone_id),
+        graph.pole_joint.get(&bone_id),
+    ) {
+        (Some(base_joint), Some(pole_joint)) => data
+            .chains
+            .required_positions
+            .get(base_joint)
+            .is_some_and(|reqs| reqs.contains(pole_joint)),
+        _ => false,
+    }
+}
+
+/// Computes the local rotation of a bone which points its child joint at the global position `target`.
+/// The local transform of the bone lives in the space of its parent, so the target is moved into that space first.
+/// The bone is swung away from `ref_rot` by the shortest arc, so it keeps the twist of `ref_rot`.
+pub fn aim_bone(
+    par_tf_global: &GlobalTransform,
+    bone_tf_local: &Transform,
+    ref_rot: Quat,
+    child_translation: Vec3,
+    target: Vec3,
+) -> Quat {
+    let ref_rot = ref_rot.normalize();
+    let target_local = par_tf_global.affine().inverse().transform_point3(target);
+    let old_dir = ref_rot.mul_vec3(bone_tf_local.scale * child_translation);
+    let new_dir = target_local - bone_tf_local.translation;
+
+    // degenerate bones or targets on top of the base joint give no direction
+    if old_dir.length_squared() < f32::EPSILON || new_dir.length_squared() < f32::EPSILON {
+        return ref_rot;
+    }
+
+    let swing = Quat::from_rotation_arc(old_dir.normalize(), new_dir.normalize());
+    (swing * ref_rot).normalize()
+}
+
+pub fn smooth_bone_rotations(
+    mut armatures: Query<(Entity, &mut IkSmoothing)>,
+    mut bones: Query<&mut Transform, With<Bone>>,
+    index: Res<BoneIndex>,
+    graph: Res<ArmatureGraph>,
+    data: Res<IkData>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+
+    for (root_id, mut smoothing) in armatures.iter_mut() {
+        let armature = match index.armatures.get(&root_id) {
+            Some(armature) => armature,
+            None => continue, // not a root bone
+        };
+
+        // exponential decay towards the solution, independent of the frame rate
+        let factor = if smoothing.half_life > 0.0 {
+            1.0 - 0.5_f32.powf(dt / smoothing.half_life)
+        } else {
+            1.0
+        };
+        let max_angle = smoothing.max_angular_speed.map(|speed| speed * dt);
+
+        let mut last_rotations = HashMap::<Entity, Quat>::new();
+        for &bone_id in armature.bones.iter() {
+            // only smooth bones that are part of an IK chain, the rest stays as animated
+            if !is_chain_bone(&graph, &data, bone_id) {
+                continue;
+            }
+
+            let mut bone_tf = bones.get_mut(bone_id).unwrap();
+            if let Some(last_rot) = smoothing.last_rotations.get(&bone_id) {
+                let target_rot = bone_tf.rotation;
+                let mut rot = last_rot.slerp(target_rot, factor);
+                if let Some(max_angle) = max_angle {
+                    let angle = last_rot.angle_between(rot);
+                    if angle > max_angle {
+                        rot = last_rot.slerp(rot, max_angle / angle);
+                    }
                 }
+                bone_tf.rotation = rot.normalize();
             }
+            last_rotations.insert(bone_id, bone_tf.rotation);
+        }
+        smoothing.last_rotations = last_rotations;
+    }
+}
+
+/// Decomposes a rotation into a swing (perpendicular to `axis`) and a twist (around `axis`), so that
+/// `rot == swing * twist`.
+pub fn swing_twist(rot: Quat, axis: Vec3) -> (Quat, Quat) {
+    let axis = axis.normalize();
+    let projected = axis * Vec3::new(rot.x, rot.y, rot.z).dot(axis);
+    let twist = Quat::from_xyzw(projected.x, projected.y, projected.z, rot.w);
+    // a swing of 180 degrees has no defined twist
+    let twist = if twist.length_squared() < f32::EPSILON {
+        Quat::IDENTITY
+    } else {
+        twist.normalize()
+    };
+    let swing = rot * twist.inverse();
+    (swing, twist)
+}
+
+/// The signed angle in [-PI, PI] a rotation twists around `axis`, see [`swing_twist`].
+pub fn twist_angle(rot: Quat, axis: Vec3) -> f32 {
+    let (_, twist) = swing_twist(rot, axis);
+    let angle = 2.0
+        * Vec3::new(twist.x, twist.y, twist.z)
+            .dot(axis.normalize())
+            .atan2(twist.w);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle < -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+pub fn distribute_twist(
+    mut helpers: Query<(Entity, &mut TwistHelper)>,
+    mut bones: Query<&mut Transform, With<Bone>>,
+    rest_poses: Query<&RestPose>,
+) {
+    for (helper_id, mut helper) in helpers.iter_mut() {
+        let source_rest_rot = match rest_poses
+            .iter()
+            .find_map(|rest_pose| rest_pose.transforms.get(&helper.source))
+        {
+            Some(rest_tf) => rest_tf.rotation,
+            None => continue,
+        };
+        let source_rot = match bones.get(helper.source) {
+            Ok(source_tf) => source_tf.rotation,
+            Err(_) => continue,
+        };
+
+        // twist of the source bone relative to its rest rotation, in its local space
+        let axis = helper.axis.normalize();
+        let angle = twist_angle(source_rest_rot.inverse() * source_rot, axis);
+        let helper_twist = Quat::from_axis_angle(axis, angle * helper.weight);
+
+        let mut helper_tf = match bones.get_mut(helper_id) {
+            Ok(helper_tf) => helper_tf,
+            Err(_) => continue,
+        };
+        // undo the twist of the last frame, unless somebody else (e.g. an animation) has rotated the helper since
+        let base_rot = match helper.last_rotation {
+            Some((original, applied)) if applied == helper_tf.rotation => original,
+            _ => helper_tf.rotation,
+        };
+        helper_tf.rotation = base_rot * helper_twist;
+        helper.last_rotation = Some((base_rot, helper_tf.rotation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPS: f32 = 0.001;
+
+    fn aimed_pole(
+        par_tf_global: &GlobalTransform,
+        bone_tf_local: Transform,
+        child_translation: Vec3,
+        target: Vec3,
+    ) -> Vec3 {
+        let rotation = aim_bone(
+            par_tf_global,
+            &bone_tf_local,
+            bone_tf_local.rotation,
+            child_translation,
+            target,
+        );
+        let bone_tf_global = par_tf_global.mul_transform(bone_tf_local.with_rotation(rotation));
+        bone_tf_global.transform_point(child_translation)
+    }
+
+    #[test]
+    fn aim_bone_without_parent() {
+        let pole = aimed_pole(
+            &GlobalTransform::IDENTITY,
+            Transform::IDENTITY,
+            Vec3::Y,
+            Vec3::X * 5.0,
+        );
+        assert!(pole.distance(Vec3::X) < EPS);
+    }
+
+    #[test]
+    fn aim_bone_with_rotated_parent() {
+        let par_tf_global = GlobalTransform::from(
+            Transform::from_xyz(1.0, 2.0, 3.0).with_rotation(Quat::from_rotation_z(FRAC_PI_2)),
+        );
+        let bone_tf_local =
+            Transform::from_xyz(0.0, 1.0, 0.0).with_rotation(Quat::from_rotation_x(0.3));
+        let base = par_tf_global.transform_point(bone_tf_local.translation);
+        let target = base + Vec3::new(0.0, 0.0, 2.0);
+
+        let pole = aimed_pole(&par_tf_global, bone_tf_local, Vec3::Y, target);
+        assert!(pole.distance(base + Vec3::Z) < EPS);
+    }
+
+    #[test]
+    fn aim_bone_with_rotated_and_scaled_parent() {
+        // glTF rigs like the mannequin often have a scaled and rotated armature
+        let par_tf_global = GlobalTransform::from(
+            Transform::from_rotation(Quat::from_rotation_x(FRAC_PI_2))
+                .with_scale(Vec3::splat(0.01)),
+        );
+        let bone_tf_local = Transform::from_xyz(0.0, 100.0, 0.0);
+        let base = par_tf_global.transform_point(bone_tf_local.translation);
+        let target = base + Vec3::new(0.5, 0.5, 0.0);
+
+        let pole = aimed_pole(&par_tf_global, bone_tf_local, Vec3::Y * 50.0, target);
+        let expected = base + Vec3::new(0.5, 0.5, 0.0).normalize() * 0.5;
+        assert!(pole.distance(expected) < EPS);
+    }
+
+    #[test]
+    fn aim_bone_keeps_reference_rotation_when_aligned() {
+        let ref_rot = Quat::from_rotation_y(1.0);
+        let rotation = aim_bone(
+            &GlobalTransform::IDENTITY,
+            &Transform::IDENTITY,
+            ref_rot,
+            Vec3::Y,
+            Vec3::Y * 3.0,
+        );
+        assert!(rotation.angle_between(ref_rot) < EPS);
+    }
+
+    #[test]
+    fn world_bone_length_with_uniform_parent_scale() {
+        let par_tf =
+            Transform::from_rotation(Quat::from_rotation_z(1.0)).with_scale(Vec3::splat(2.0));
+        let bone_tf = Transform::from_xyz(0.0, 1.0, 0.0);
+        let bone_gt = GlobalTransform::from(par_tf).mul_transform(bone_tf);
+        for dir in [Vec3::X, Vec3::Y, Vec3::new(1.0, 2.0, 3.0)] {
+            assert!((world_bone_length(&bone_gt, &bone_tf, 1.5, dir) - 3.0).abs() < EPS);
         }
     }
+
+    #[test]
+    fn world_bone_length_with_non_uniform_parent_scale() {
+        let par_tf = Transform::from_scale(Vec3::new(1.0, 2.0, 4.0));
+        let bone_tf = Transform::from_xyz(0.0, 1.0, 0.0).with_rotation(Quat::from_rotation_x(0.5));
+        let bone_gt = GlobalTransform::from(par_tf).mul_transform(bone_tf);
+        assert!((world_bone_length(&bone_gt, &bone_tf, 1.0, Vec3::X) - 1.0).abs() < EPS);
+        assert!((world_bone_length(&bone_gt, &bone_tf, 1.0, -Vec3::Y) - 2.0).abs() < EPS);
+        assert!((world_bone_length(&bone_gt, &bone_tf, 1.0, Vec3::Z) - 4.0).abs() < EPS);
+    }
 }
@@ -0,0 +1,267 @@
+//! Headless test harness for the solver: builds a minimal app, spawns armatures from compact descriptions
+//! and checks the solved poses.
+#![allow(dead_code)] // not every test uses every helper
+
+use bevy::{
+    prelude::*,
+    time::TimeUpdateStrategy,
+    utils::{Duration, HashMap, Instant},
+};
+use bevy_ik::{
+    Bone, BoneBundle, Grip, GripFinger, GripShape, IkGoal, IkGoalBundle, InverseKinematicsPlugin,
+};
+
+/// Fingers spawned by [`TestRig::spawn_hand`], by name and sideways offset of their middle joint.
+pub const FINGERS: [(&str, f32); 3] = [("index", -0.02), ("middle", 0.), ("ring", 0.02)];
+
+/// The length of a leg spawned by [`TestRig::spawn_leg`], from the hip to the foot.
+pub fn leg_length(side: f32, shin: f32) -> f32 {
+    Vec3::new(side, shin, 0.05).length() + Vec2::new(shin, 0.05).length()
+}
+
+pub struct TestRig {
+    pub app: App,
+    /// the entity all armatures are spawned below
+    pub armature: Entity,
+    /// bone names and their ids
+    pub bones: HashMap<String, Entity>,
+    /// bone ids and their parent bone id
+    pub bone_parents: HashMap<Entity, Entity>,
+}
+
+impl TestRig {
+    pub fn new() -> Self {
+        Self::with_plugin(InverseKinematicsPlugin::default())
+    }
+
+    pub fn with_plugin(plugin: InverseKinematicsPlugin) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(plugin);
+        let armature = app.world.spawn(TransformBundle::default()).id();
+        Self {
+            app,
+            armature,
+            bones: HashMap::new(),
+            bone_parents: HashMap::new(),
+        }
+    }
+
+    pub fn set_armature_transform(&mut self, transform: Transform) -> &mut Self {
+        *self.app.world.get_mut::<Transform>(self.armature).unwrap() = transform;
+        self
+    }
+
+    /// Spawns a chain of bones. Each bone is given by its name and its translation relative to the previous bone.
+    /// The first bone is attached to the bone named `parent`, or to the armature entity.
+    pub fn spawn_chain(&mut self, parent: Option<&str>, chain: &[(&str, Vec3)]) -> &mut Self {
+        let mut parent_id = parent.map_or(self.armature, |name| self.bone(name));
+        for (name, translation) in chain.iter() {
+            let bone_id = self
+                .app
+                .world
+                .spawn(BoneBundle {
+                    bone: Bone {
+                        name: name.to_string(),
+                    },
+                    transform: Transform::from_translation(*translation),
+                    ..default()
+                })
+                .id();
+            self.app
+                .world
+                .entity_mut(parent_id)
+                .push_children(&[bone_id]);
+            if parent_id != self.armature {
+                self.bone_parents.insert(bone_id, parent_id);
+            }
+            self.bones.insert(name.to_string(), bone_id);
+            parent_id = bone_id;
+        }
+        self
+    }
+
+    /// Spawns a leg with a slightly bent knee: thigh, shin, foot and toes bones, named with the given prefix.
+    /// The thigh starts at the joint of `hips`, so both legs of a biped can share it, because all child bones
+    /// of a bone have to start at the same joint. The knee and the foot are moved sideways by `side`,
+    /// the foot ends up twice the `shin` length below the hip.
+    pub fn spawn_leg(
+        &mut self,
+        hips: Option<&str>,
+        prefix: &str,
+        side: f32,
+        shin: f32,
+    ) -> &mut Self {
+        self.spawn_chain(
+            hips,
+            &[
+                (&format!("{prefix}thigh"), Vec3::ZERO),
+                (&format!("{prefix}shin"), Vec3::new(side, -shin, 0.05)),
+                (&format!("{prefix}foot"), Vec3::new(0., -shin, -0.05)),
+                (&format!("{prefix}toes"), Vec3::Z * 0.1),
+            ],
+        )
+    }
+
+    /// Spawns a palm with the straight [`FINGERS`] branching off its end, each with base, mid, end and tip bones.
+    pub fn spawn_hand(&mut self) -> &mut Self {
+        self.spawn_chain(None, &[("palm", Vec3::ZERO)]);
+        for (finger, x) in FINGERS {
+            self.spawn_chain(
+                Some("palm"),
+                &[
+                    (&format!("{finger}_base"), Vec3::Y * 0.1),
+                    (&format!("{finger}_mid"), Vec3::new(x, 0.04, 0.)),
+                    (&format!("{finger}_end"), Vec3::Y * 0.03),
+                    (&format!("{finger}_tip"), Vec3::Y * 0.03),
+                ],
+            );
+        }
+        self
+    }
+
+    /// Spawns a [`Grip`] with the given shape, and the fingertip goals of a hand from [`TestRig::spawn_hand`]
+    /// grabbing it with one contact per finger.
+    pub fn grab(&mut self, shape: GripShape, position: Vec3) -> Vec<Entity> {
+        let grip = self
+            .app
+            .world
+            .spawn((
+                Grip { shape },
+                TransformBundle::from_transform(Transform::from_translation(position)),
+            ))
+            .id();
+        FINGERS
+            .iter()
+            .enumerate()
+            .map(|(contact, (finger, _))| {
+                let tip = format!("{finger}_tip");
+                let goal_id = self.spawn_goal(&tip, 3, self.bone_position(&tip));
+                self.app.world.entity_mut(goal_id).insert(GripFinger {
+                    grip,
+                    contact,
+                    pad: 0.,
+                });
+                goal_id
+            })
+            .collect()
+    }
+
+    pub fn spawn_goal(&mut self, bone: &str, chain_length: u32, position: Vec3) -> Entity {
+        let target_bone = self.bone(bone);
+        self.app
+            .world
+            .spawn(IkGoalBundle {
+                goal: IkGoal {
+                    target_bone,
+                    chain_length,
+                },
+                transform: Transform::from_translation(position),
+                global_transform: GlobalTransform::from_translation(position),
+            })
+            .id()
+    }
+
+    pub fn move_goal(&mut self, goal_id: Entity, position: Vec3) {
+        *self.app.world.get_mut::<Transform>(goal_id).unwrap() =
+            Transform::from_translation(position);
+    }
+
+    pub fn step(&mut self, frames: usize) -> &mut Self {
+        for _ in 0..frames {
+            self.app.update();
+        }
+        self
+    }
+
+    /// Steps with a fixed frame time instead of the real time, e.g. to compare frame rates.
+    pub fn step_seconds(&mut self, frames: usize, delta_seconds: f32) -> &mut Self {
+        for _ in 0..frames {
+            let last_update = self
+                .app
+                .world
+                .resource::<Time>()
+                .last_update()
+                .unwrap_or_else(Instant::now);
+            self.app
+                .world
+                .insert_resource(TimeUpdateStrategy::ManualInstant(
+                    last_update + Duration::from_secs_f32(delta_seconds),
+                ));
+            self.app.update();
+        }
+        self
+    }
+
+    pub fn bone(&self, name: &str) -> Entity {
+        *self
+            .bones
+            .get(name)
+            .unwrap_or_else(|| panic!("no bone named {name}"))
+    }
+
+    pub fn bone_position(&self, name: &str) -> Vec3 {
+        self.app
+            .world
+            .get::<GlobalTransform>(self.bone(name))
+            .unwrap()
+            .translation()
+    }
+
+    /// Global positions of all bones by name.
+    pub fn bone_positions(&self) -> HashMap<String, Vec3> {
+        self.bones
+            .keys()
+            .map(|name| (name.clone(), self.bone_position(name)))
+            .collect()
+    }
+
+    /// Distances between each bone and its parent bone by name of the child bone.
+    pub fn bone_lengths(&self) -> HashMap<String, f32> {
+        self.bones
+            .iter()
+            .filter_map(|(name, bone_id)| {
+                let parent_id = self.bone_parents.get(bone_id)?;
+                let parent_pos = self.app.world.get::<GlobalTransform>(*parent_id)?;
+                Some((
+                    name.clone(),
+                    parent_pos.translation().distance(self.bone_position(name)),
+                ))
+            })
+            .collect()
+    }
+
+    pub fn goal_distance(&self, goal_id: Entity) -> f32 {
+        let goal = self.app.world.get::<IkGoal>(goal_id).unwrap();
+        let goal_pos = self
+            .app
+            .world
+            .get::<GlobalTransform>(goal_id)
+            .unwrap()
+            .translation();
+        let bone_pos = self
+            .app
+            .world
+            .get::<GlobalTransform>(goal.target_bone)
+            .unwrap()
+            .translation();
+        goal_pos.distance(bone_pos)
+    }
+
+    pub fn assert_goal_reached(&self, goal_id: Entity, tolerance: f32) {
+        let dist = self.goal_distance(goal_id);
+        assert!(dist < tolerance, "goal {goal_id:?} is {dist} away");
+    }
+
+    /// Asserts that the bone lengths are the same as the given ones, e.g. from before solving.
+    pub fn assert_bone_lengths(&self, expected: &HashMap<String, f32>, tolerance: f32) {
+        for (name, length) in self.bone_lengths().iter() {
+            let expected_length = expected.get(name).unwrap();
+            assert!(
+                (length - expected_length).abs() < tolerance,
+                "bone {name} has length {length}, expected {expected_length}"
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{FlatGround, FootPlacement, FootPlacementPlugin, Ground, Pelvis};
+use common::{leg_length, TestRig};
+
+const TOLERANCE: f32 = 0.01;
+
+/// a single leg with a slightly bent knee below the hips, with its foot bone at height 0.1
+fn leg(ground: Ground) -> TestRig {
+    let mut rig = TestRig::new();
+    rig.app
+        .add_plugin(FootPlacementPlugin)
+        .insert_resource(ground);
+    rig.spawn_chain(None, &[("hips", Vec3::Y)])
+        .spawn_leg(Some("hips"), "", 0.1, 0.45);
+    rig.step(1);
+    rig
+}
+
+fn spawn_foot_goal(rig: &mut TestRig) -> Entity {
+    let goal_id = rig.spawn_goal("foot", 2, rig.bone_position("foot"));
+    rig.app.world.entity_mut(goal_id).insert(FootPlacement {
+        foot_height: 0.1,
+        ..default()
+    });
+    goal_id
+}
+
+#[test]
+fn foot_steps_onto_raised_ground() {
+    let mut rig = leg(Ground::new(FlatGround { height: 0.3 }));
+    let goal_id = spawn_foot_goal(&mut rig);
+    rig.step(3);
+
+    let goal_tf = rig.app.world.get::<Transform>(goal_id).unwrap();
+    assert!(goal_tf.translation.distance(Vec3::new(0.1, 0.4, 0.)) < TOLERANCE);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn pelvis_is_lowered_onto_lower_ground() {
+    let mut rig = leg(Ground::new(FlatGround { height: -0.3 }));
+    let hips = rig.bone("hips");
+    rig.app.world.entity_mut(hips).insert(Pelvis::default());
+    let goal_id = spawn_foot_goal(&mut rig);
+    rig.step(3);
+
+    // the hips have to go down to 98% of the leg length above the goal at -0.2
+    let leg_length = leg_length(0.1, 0.45);
+    let pelvis = rig.app.world.get::<Pelvis>(hips).unwrap();
+    assert!((pelvis.offset - (-0.2 + leg_length * 0.98 - 1.)).abs() < TOLERANCE);
+    assert!((rig.bone_position("hips").y - (1. + pelvis.offset)).abs() < TOLERANCE);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+
+    // the offset doesn't accumulate over frames
+    let offset = pelvis.offset;
+    rig.step(3);
+    let pelvis = rig.app.world.get::<Pelvis>(hips).unwrap();
+    assert!((pelvis.offset - offset).abs() < TOLERANCE);
+    assert!((rig.bone_position("hips").y - (1. + offset)).abs() < TOLERANCE);
+}
+
+#[test]
+fn foot_is_aligned_to_slope() {
+    let mut rig = leg(Ground::new(|pos: Vec2| 0.2 * pos.x));
+    let goal_id = spawn_foot_goal(&mut rig);
+    rig.step(3);
+
+    let normal = Vec3::new(-0.2, 1., 0.).normalize();
+    let foot_gt = rig
+        .app
+        .world
+        .get::<GlobalTransform>(rig.bone("foot"))
+        .unwrap();
+    let (_, foot_rot, _) = foot_gt.to_scale_rotation_translation();
+    assert!((foot_rot * Vec3::Y).distance(normal) < TOLERANCE);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn pelvis_height_is_stable_on_slope() {
+    let mut rig = leg(Ground::new(|pos: Vec2| -0.3 - 0.5 * pos.x));
+    let hips = rig.bone("hips");
+    rig.app.world.entity_mut(hips).insert(Pelvis::default());
+    let goal_id = spawn_foot_goal(&mut rig);
+    rig.step(3);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+
+    // the offset is computed from the hip without it, so it doesn't feed back on itself
+    let height = rig.bone_position("hips").y;
+    let foot = rig.bone_position("foot");
+    assert!(height < 1. - 0.1);
+    for _ in 0..100 {
+        rig.step(1);
+        assert!((rig.bone_position("hips").y - height).abs() < TOLERANCE);
+    }
+    assert!(rig.bone_position("foot").distance(foot) < TOLERANCE);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn pelvis_follows_a_tilting_armature() {
+    let mut rig = leg(Ground::new(FlatGround { height: -0.3 }));
+    let hips = rig.bone("hips");
+    rig.app.world.entity_mut(hips).insert(Pelvis::default());
+    let goal_id = spawn_foot_goal(&mut rig);
+    rig.step(3);
+
+    // the armature tilts and grows, the offset of the last frame is contained in the new space of the armature
+    let leg_length = leg_length(0.1, 0.45);
+    for frame in 1..20 {
+        let scale = 1. + 0.02 * frame as f32;
+        rig.set_armature_transform(
+            Transform::from_rotation(Quat::from_rotation_z(0.02 * frame as f32))
+                .with_scale(Vec3::splat(scale)),
+        );
+        rig.step(1);
+
+        // the hip is lowered just enough for the stretched leg to reach the goal
+        let goal_pos = rig.app.world.get::<Transform>(goal_id).unwrap().translation;
+        let reach = rig.bone_position("thigh").distance(goal_pos);
+        assert!((reach - leg_length * scale * 0.98).abs() < TOLERANCE);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+}
+
+#[test]
+fn foot_is_aligned_below_scaled_parent() {
+    let mut rig = leg(Ground::new(|pos: Vec2| 0.2 * pos.x));
+    let hips = rig.bone("hips");
+    rig.app.world.get_mut::<Transform>(hips).unwrap().scale = Vec3::new(1., 2., 1.);
+    let goal_id = spawn_foot_goal(&mut rig);
+    rig.step(3);
+
+    let normal = Vec3::new(-0.2, 1., 0.).normalize();
+    let foot_gt = rig
+        .app
+        .world
+        .get::<GlobalTransform>(rig.bone("foot"))
+        .unwrap();
+    let up = foot_gt.affine().transform_vector3(Vec3::Y).normalize();
+    assert!(up.distance(normal) < TOLERANCE);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
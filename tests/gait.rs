@@ -0,0 +1,134 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{FlatGround, Gait, GaitPlugin, Ground, Leg};
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+/// hips with two slightly bent legs, the feet at height 0.1. Each leg has a goal in its own group.
+fn biped() -> (TestRig, [Entity; 2]) {
+    // steps land immediately, so the tests don't depend on the frame time
+    biped_with_step_duration(0.)
+}
+
+fn biped_with_step_duration(step_duration: f32) -> (TestRig, [Entity; 2]) {
+    let mut rig = TestRig::new();
+    rig.app.add_plugin(GaitPlugin);
+    rig.spawn_chain(None, &[("hips", Vec3::Y)])
+        .spawn_leg(Some("hips"), "left_", -0.1, 0.45)
+        .spawn_leg(Some("hips"), "right_", 0.1, 0.45);
+    let body = rig.armature;
+    rig.app.world.entity_mut(body).insert(Gait {
+        step_distance: 0.2,
+        step_duration,
+        ..default()
+    });
+    rig.step(1);
+
+    let goals = [("left", -0.1, 0), ("right", 0.1, 1)].map(|(side, x, group)| {
+        let foot = format!("{side}_foot");
+        let goal_id = rig.spawn_goal(&foot, 2, rig.bone_position(&foot));
+        rig.app.world.entity_mut(goal_id).insert(Leg {
+            body,
+            rest_offset: Vec3::new(x, 0.1, 0.),
+            group,
+            planted: None,
+            step: None,
+        });
+        goal_id
+    });
+    rig.step(2);
+    (rig, goals)
+}
+
+fn goal_position(rig: &TestRig, goal_id: Entity) -> Vec3 {
+    rig.app.world.get::<Transform>(goal_id).unwrap().translation
+}
+
+#[test]
+fn planted_feet_stay_while_body_moves_a_bit() {
+    let (mut rig, goals) = biped();
+    let planted = goals.map(|goal_id| goal_position(&rig, goal_id));
+
+    rig.set_armature_transform(Transform::from_xyz(0., 0., 0.1));
+    rig.step(3);
+
+    for (goal_id, planted) in goals.into_iter().zip(planted) {
+        assert!(goal_position(&rig, goal_id).distance(planted) < TOLERANCE);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+}
+
+#[test]
+fn legs_take_turns_stepping() {
+    let (mut rig, goals) = biped();
+    let rest = |goal_id| {
+        let x = rig.app.world.get::<Leg>(goal_id).unwrap().rest_offset.x;
+        Vec3::new(x, 0.1, 0.3)
+    };
+    let rests = goals.map(rest);
+
+    rig.set_armature_transform(Transform::from_xyz(0., 0., 0.3));
+    rig.step(1);
+    let stepped = goals
+        .iter()
+        .zip(rests)
+        .filter(|(goal_id, rest)| goal_position(&rig, **goal_id).distance(*rest) < TOLERANCE)
+        .count();
+    assert_eq!(stepped, 1);
+
+    rig.step(1);
+    for (goal_id, rest) in goals.into_iter().zip(rests) {
+        assert!(goal_position(&rig, goal_id).distance(rest) < TOLERANCE);
+    }
+    rig.step(2);
+    for goal_id in goals {
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+}
+
+#[test]
+fn feet_step_on_an_arc_one_group_after_the_other() {
+    let (mut rig, goals) = biped_with_step_duration(0.2);
+    let [left, right] = goals;
+    let planted = goals.map(|goal_id| goal_position(&rig, goal_id));
+
+    // half way through the step of the first group, the foot is at the top of the arc
+    rig.set_armature_transform(Transform::from_xyz(0., 0., 0.3));
+    rig.step_seconds(2, 0.05);
+    let step_height = Gait::default().step_height;
+    let left_pos = goal_position(&rig, left);
+    assert!((left_pos.y - (0.1 + step_height)).abs() < TOLERANCE);
+    assert!((left_pos.z - 0.15).abs() < TOLERANCE);
+    assert!(goal_position(&rig, right).distance(planted[1]) < TOLERANCE);
+    rig.assert_goal_reached(left, TOLERANCE);
+
+    // the second group waits until the first has landed
+    rig.step_seconds(2, 0.05);
+    assert!(goal_position(&rig, left).distance(Vec3::new(-0.1, 0.1, 0.3)) < TOLERANCE);
+    assert!(goal_position(&rig, right).distance(planted[1]) < TOLERANCE);
+    rig.step_seconds(4, 0.05);
+    assert!(goal_position(&rig, right).distance(Vec3::new(0.1, 0.1, 0.3)) < TOLERANCE);
+
+    // after a pause, the groups keep taking turns
+    rig.step_seconds(5, 0.05);
+    rig.set_armature_transform(Transform::from_xyz(0., 0., 0.6));
+    rig.step_seconds(2, 0.05);
+    assert!(goal_position(&rig, left).y > 0.1 + step_height / 2.);
+    assert!(goal_position(&rig, right).distance(Vec3::new(0.1, 0.1, 0.3)) < TOLERANCE);
+}
+
+#[test]
+fn feet_step_onto_the_ground() {
+    let (mut rig, goals) = biped();
+    rig.app
+        .insert_resource(Ground::new(FlatGround { height: 0.2 }));
+    rig.set_armature_transform(Transform::from_xyz(0., 0., 0.3));
+    rig.step(2);
+
+    // the feet keep their height above the ground
+    for goal_id in goals {
+        assert!((goal_position(&rig, goal_id).y - 0.3).abs() < TOLERANCE);
+    }
+}
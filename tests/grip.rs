@@ -0,0 +1,50 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{GripPlugin, GripShape};
+use common::{TestRig, FINGERS};
+
+const TOLERANCE: f32 = 0.005;
+
+/// a palm with three straight fingers branching off its end
+fn hand() -> TestRig {
+    let mut rig = TestRig::new();
+    rig.app.add_plugin(GripPlugin);
+    rig.spawn_hand().step(1);
+    rig
+}
+
+#[test]
+fn fingertips_touch_sphere() {
+    let mut rig = hand();
+    let lengths = rig.bone_lengths();
+    let center = Vec3::new(0., 0.19, 0.05);
+    let goals = rig.grab(GripShape::Sphere { radius: 0.03 }, center);
+    rig.step(5);
+
+    for ((finger, _), goal_id) in FINGERS.iter().zip(goals) {
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+        let dist = rig.bone_position(&format!("{finger}_tip")).distance(center);
+        assert!((dist - 0.03).abs() < TOLERANCE, "{finger} is {dist} away");
+    }
+    rig.assert_bone_lengths(&lengths, TOLERANCE);
+}
+
+#[test]
+fn fingertips_reach_contacts() {
+    let mut rig = hand();
+    let contacts = vec![
+        Vec3::new(-0.02, 0.06, 0.04),
+        Vec3::new(0., 0.07, 0.04),
+        Vec3::new(0.02, 0.06, 0.04),
+    ];
+    let origin = Vec3::Y * 0.12;
+    let goals = rig.grab(GripShape::Contacts(contacts.clone()), origin);
+    rig.step(5);
+
+    for ((finger, _), (goal_id, contact)) in FINGERS.iter().zip(goals.into_iter().zip(contacts)) {
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+        let tip = rig.bone_position(&format!("{finger}_tip"));
+        assert!(tip.distance(origin + contact) < TOLERANCE);
+    }
+}
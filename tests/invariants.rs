@@ -0,0 +1,186 @@
+//! Invariants of solved poses over randomly generated armatures and goals.
+mod common;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ik::{ArmatureGraph, IkData, IkSettings};
+use common::TestRig;
+use proptest::prelude::*;
+
+const TOLERANCE: f32 = 0.01;
+
+/// A random bone offset with a length between 0.5 and 2.
+fn offset() -> impl Strategy<Value = Vec3> {
+    (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0, 0.5f32..2.0)
+        .prop_filter_map("degenerate direction", |(x, y, z, length)| {
+            Vec3::new(x, y, z).try_normalize().map(|dir| dir * length)
+        })
+}
+
+/// A random unit direction.
+fn direction() -> impl Strategy<Value = Vec3> {
+    (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0)
+        .prop_filter_map("degenerate direction", |(x, y, z)| {
+            Vec3::new(x, y, z).try_normalize()
+        })
+}
+
+/// A random bone tree, given as the parent index of each bone (the first bone is the root).
+/// All children of a bone share the same joint, so the offset belongs to the parent.
+fn armature() -> impl Strategy<Value = (Vec<usize>, Vec<Vec3>)> {
+    (2usize..8).prop_flat_map(|num_bones| {
+        let parents = (1..num_bones)
+            .map(|i| 0..i)
+            .collect::<Vec<_>>()
+            .prop_map(|parents| [vec![0], parents].concat());
+        let offsets = prop::collection::vec(offset(), num_bones);
+        (parents, offsets)
+    })
+}
+
+fn spawn_armature(rig: &mut TestRig, parents: &[usize], offsets: &[Vec3]) {
+    rig.spawn_chain(None, &[("0", Vec3::ZERO)]);
+    for (i, parent) in parents.iter().enumerate().skip(1) {
+        rig.spawn_chain(
+            Some(&parent.to_string()),
+            &[(&i.to_string(), offsets[*parent])],
+        );
+    }
+}
+
+fn leaves(parents: &[usize]) -> Vec<usize> {
+    (1..parents.len())
+        .filter(|i| !parents[1..].contains(i))
+        .collect()
+}
+
+fn local_transforms(rig: &TestRig) -> HashMap<Entity, Transform> {
+    rig.bones
+        .values()
+        .map(|bone_id| (*bone_id, *rig.app.world.get::<Transform>(*bone_id).unwrap()))
+        .collect()
+}
+
+/// Bones between two solved joints, which are the only bones the solver may rotate.
+fn chain_bones(rig: &TestRig) -> Vec<Entity> {
+    let graph = rig.app.world.resource::<ArmatureGraph>();
+    let data = rig.app.world.resource::<IkData>();
+    rig.bones
+        .values()
+        .copied()
+        .filter(
+            |bone_id| match (graph.base_joint.get(bone_id), graph.pole_joint.get(bone_id)) {
+                (Some(base_joint), Some(pole_joint)) => data
+                    .chains
+                    .required_positions
+                    .get(base_joint)
+                    .is_some_and(|reqs| reqs.contains(pole_joint)),
+                _ => false,
+            },
+        )
+        .collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn solved_poses_keep_structure(
+        (parents, offsets) in armature(),
+        goals in prop::collection::vec((any::<prop::sample::Index>(), 0u32..8, offset()), 1..3),
+    ) {
+        let mut rig = TestRig::new();
+        spawn_armature(&mut rig, &parents, &offsets);
+        rig.step(1);
+        let lengths = rig.bone_lengths();
+        let positions = rig.bone_positions();
+        let transforms = local_transforms(&rig);
+
+        // at most one goal per leaf bone
+        let leaves = leaves(&parents);
+        let mut goal_bones = HashMap::new();
+        for (leaf, chain_length, offset) in goals.iter() {
+            let bone = leaf.get(&leaves).to_string();
+            let position = positions[&bone] + *offset;
+            goal_bones.insert(bone, (*chain_length, position));
+        }
+        for (bone, (chain_length, position)) in goal_bones.iter() {
+            rig.spawn_goal(bone, *chain_length, *position);
+        }
+        // a single frame, so the solve starts from the spawned pose
+        rig.step(1);
+
+        let graph = rig.app.world.resource::<ArmatureGraph>();
+        let data = rig.app.world.resource::<IkData>();
+        let chain_bones = chain_bones(&rig);
+
+        // chain bones keep their length in the solver, all bones keep their length in the applied pose
+        for bone_id in chain_bones.iter() {
+            let length = data.bone_lengths[bone_id];
+            let base_pos = data.joint_positions[&graph.base_joint[bone_id]];
+            let pole_pos = data.joint_positions[&graph.pole_joint[bone_id]];
+            prop_assert!((base_pos.distance(pole_pos) - length).abs() < TOLERANCE);
+        }
+        rig.assert_bone_lengths(&lengths, TOLERANCE);
+
+        // roots and joints outside of any chain keep their position in the solver
+        for (name, bone_id) in rig.bones.iter() {
+            let base_joint = graph.base_joint[bone_id];
+            if data.chains.roots.contains(&base_joint) || !data.chains.contains(base_joint) {
+                prop_assert!(data.joint_positions[&base_joint].distance(positions[name]) < TOLERANCE);
+            }
+        }
+
+        // bones outside of any chain keep their local transform
+        for (bone_id, transform) in local_transforms(&rig).iter() {
+            if !chain_bones.contains(bone_id) {
+                prop_assert_eq!(transform, &transforms[bone_id]);
+            }
+        }
+    }
+
+    #[test]
+    fn reachable_goals_converge(
+        offsets in prop::collection::vec(offset(), 2..8),
+        directions in prop::collection::vec(direction(), 8),
+        chain_length in 0usize..8,
+    ) {
+        // a single chain with a goal at the leaf
+        let mut rig = TestRig::new();
+        let names = (0..offsets.len()).map(|i| i.to_string()).collect::<Vec<_>>();
+        let chain = names
+            .iter()
+            .zip([Vec3::ZERO].iter().chain(offsets.iter()))
+            .map(|(name, offset)| (name.as_str(), *offset))
+            .collect::<Vec<_>>();
+        rig.spawn_chain(None, &chain);
+        rig.step(1);
+
+        // the goal is reachable, if it is the end of the chain bones pointing into arbitrary directions
+        let chain_length = chain_length.min(offsets.len() - 1);
+        let leaf = offsets.len() - 1;
+        let root = leaf - chain_length;
+        let root_pos = rig.bone_position(&names[root]);
+        let mut goal = root_pos;
+        for (offset, direction) in offsets[root..leaf].iter().zip(directions.iter()) {
+            goal += *direction * offset.length();
+        }
+        // FABRIK converges slowly at the border of the reachable shell, e.g. for chains folded onto their root.
+        // Without a chain, the goal is only reachable at the leaf itself.
+        let lengths = offsets[root..leaf].iter().map(|offset| offset.length());
+        let max_reach: f32 = lengths.clone().sum();
+        let min_reach = (2. * lengths.fold(0., f32::max) - max_reach).max(0.);
+        let distance = goal.distance(root_pos);
+        prop_assume!(
+            chain_length == 0 || (distance > min_reach + 0.1 && distance < max_reach - 0.1)
+        );
+        let goal_id = rig.spawn_goal(&names[leaf], chain_length as u32, goal);
+        rig.step(10);
+
+        let tolerance = rig.app.world.resource::<IkSettings>().goal_tolerance;
+        let graph = rig.app.world.resource::<ArmatureGraph>();
+        let data = rig.app.world.resource::<IkData>();
+        let leaf_joint = graph.base_joint[&rig.bone(&names[leaf])];
+        prop_assert!(data.joint_positions[&leaf_joint].distance(goal) < tolerance);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+}
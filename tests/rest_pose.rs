@@ -0,0 +1,117 @@
+mod common;
+
+use bevy::{ecs::system::Command, prelude::*, transform::TransformSystem};
+use bevy_ik::{CaptureRestPose, IkData, IkSmoothing, IkSystem, ResetToRestPose, RestPose};
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+fn arm() -> TestRig {
+    let mut rig = TestRig::new();
+    rig.spawn_chain(
+        None,
+        &[
+            ("shoulder", Vec3::ZERO),
+            ("upper_arm", Vec3::X),
+            ("forearm", Vec3::X),
+            ("hand", Vec3::X),
+        ],
+    );
+    rig
+}
+
+fn local_transform(rig: &TestRig, bone: &str) -> Transform {
+    *rig.app.world.get::<Transform>(rig.bone(bone)).unwrap()
+}
+
+fn rest_pose(rig: &TestRig) -> &RestPose {
+    rig.app.world.get::<RestPose>(rig.bone("shoulder")).unwrap()
+}
+
+fn set_rotation(rig: &mut TestRig, bone: &str, rotation: Quat) {
+    let bone_id = rig.bone(bone);
+    rig.app
+        .world
+        .get_mut::<Transform>(bone_id)
+        .unwrap()
+        .rotation = rotation;
+}
+
+#[test]
+fn rest_pose_is_captured_before_the_first_solve() {
+    let mut rig = arm();
+    let root_id = rig.bone("shoulder");
+    rig.app.add_system_to_stage(
+        CoreStage::PostUpdate,
+        (move |rest_poses: Query<&RestPose>| assert!(rest_poses.contains(root_id)))
+            .after(TransformSystem::TransformPropagate)
+            .before(IkSystem::Solve),
+    );
+    let spawned = local_transform(&rig, "forearm");
+    rig.spawn_goal("hand", 2, Vec3::new(1.5, 1.5, 0.));
+    rig.step(1);
+
+    // the spawned pose, not the solved one
+    assert_eq!(rest_pose(&rig).transforms[&rig.bone("forearm")], spawned);
+    assert_ne!(local_transform(&rig, "forearm"), spawned);
+}
+
+#[test]
+fn solved_bones_keep_their_rest_length() {
+    let mut rig = arm();
+    rig.step(1);
+
+    // an animation stretches the forearm after the rest pose was captured
+    let forearm_id = rig.bone("forearm");
+    rig.app
+        .world
+        .get_mut::<Transform>(forearm_id)
+        .unwrap()
+        .translation = Vec3::X * 1.5;
+    rig.spawn_goal("hand", 2, Vec3::new(1.5, 1.5, 0.));
+    rig.step(1);
+
+    let data = rig.app.world.resource::<IkData>();
+    let upper_arm_length = data.bone_lengths[&rig.bone("upper_arm")];
+    assert!((upper_arm_length - 1.).abs() < TOLERANCE);
+}
+
+#[test]
+fn capture_rest_pose_replaces_the_rest_pose() {
+    let mut rig = arm();
+    rig.step(1);
+    set_rotation(&mut rig, "forearm", Quat::from_rotation_z(0.5));
+    CaptureRestPose {
+        armature: rig.bone("shoulder"),
+    }
+    .write(&mut rig.app.world);
+
+    let rest_rotation = rest_pose(&rig).transforms[&rig.bone("forearm")].rotation;
+    assert!(rest_rotation.angle_between(Quat::from_rotation_z(0.5)) < TOLERANCE);
+}
+
+#[test]
+fn reset_to_rest_pose_restores_the_bones() {
+    let mut rig = arm();
+    let root_id = rig.bone("shoulder");
+    rig.app
+        .world
+        .entity_mut(root_id)
+        .insert(IkSmoothing::default());
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(1.5, 1.5, 0.));
+    rig.step(5);
+    rig.app.world.despawn(goal_id);
+    assert_ne!(local_transform(&rig, "forearm").rotation, Quat::IDENTITY);
+
+    ResetToRestPose { armature: root_id }.write(&mut rig.app.world);
+    for bone in ["upper_arm", "forearm", "hand"] {
+        assert_eq!(
+            local_transform(&rig, bone),
+            rest_pose(&rig).transforms[&rig.bone(bone)]
+        );
+    }
+    let smoothing = rig.app.world.get::<IkSmoothing>(root_id).unwrap();
+    assert!(smoothing.last_rotations.is_empty());
+    let data = rig.app.world.resource::<IkData>();
+    assert!(data.last_positions.values().all(|(id, _)| *id != root_id));
+}
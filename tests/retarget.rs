@@ -0,0 +1,168 @@
+mod common;
+
+use bevy::{ecs::system::Command, prelude::*, utils::HashMap};
+use bevy_ik::{CaptureRestPose, Retarget, RetargetContact, RetargetPlugin};
+use common::{leg_length, TestRig};
+
+const TOLERANCE: f32 = 0.01;
+const BONES: [&str; 5] = ["hips", "thigh", "shin", "foot", "toes"];
+
+/// a tall source leg and a short target leg, both with a slightly bent knee and the foot bone at height 0.1
+fn legs() -> TestRig {
+    let mut rig = TestRig::new();
+    rig.app.add_plugin(RetargetPlugin);
+    for (prefix, height, shin) in [("source_", 1.0, 0.45), ("target_", 0.8, 0.35)] {
+        rig.spawn_chain(None, &[(&format!("{prefix}hips"), Vec3::Y * height)])
+            .spawn_leg(Some(&format!("{prefix}hips")), prefix, 0.1, shin);
+    }
+    rig.step(1);
+    rig
+}
+
+fn retarget(rig: &mut TestRig, contacts: Vec<RetargetContact>) {
+    let bone_map: HashMap<String, String> = BONES
+        .iter()
+        .map(|bone| (format!("target_{bone}"), format!("source_{bone}")))
+        .collect();
+    let source = rig.bone("source_hips");
+    let target = rig.bone("target_hips");
+    rig.app.world.entity_mut(target).insert(Retarget {
+        source,
+        bone_map,
+        contacts,
+    });
+}
+
+fn foot_contact() -> Vec<RetargetContact> {
+    vec![RetargetContact {
+        bone: "target_foot".to_string(),
+        chain_length: 2,
+        goal: None,
+    }]
+}
+
+fn contact_goal(rig: &TestRig) -> Option<Entity> {
+    let retarget = rig.app.world.get::<Retarget>(rig.bone("target_hips"))?;
+    retarget.contacts[0].goal
+}
+
+fn rotation(rig: &TestRig, bone: &str) -> Quat {
+    rig.app
+        .world
+        .get::<Transform>(rig.bone(bone))
+        .unwrap()
+        .rotation
+}
+
+fn set_rotation(rig: &mut TestRig, bone: &str, rotation: Quat) {
+    let bone_id = rig.bone(bone);
+    rig.app
+        .world
+        .get_mut::<Transform>(bone_id)
+        .unwrap()
+        .rotation = rotation;
+}
+
+#[test]
+fn rotations_are_copied() {
+    let mut rig = legs();
+
+    // the target leg is turned outwards in its rest pose, it keeps the turn on top of the source rotations
+    let rest_rotations = [
+        ("thigh", Quat::from_rotation_y(0.4)),
+        ("shin", Quat::from_rotation_z(0.2)),
+    ];
+    for (bone, rest_rotation) in rest_rotations {
+        set_rotation(&mut rig, &format!("target_{bone}"), rest_rotation);
+    }
+    CaptureRestPose {
+        armature: rig.bone("target_hips"),
+    }
+    .write(&mut rig.app.world);
+
+    retarget(&mut rig, Vec::new());
+    set_rotation(&mut rig, "source_thigh", Quat::from_rotation_x(0.5));
+    set_rotation(&mut rig, "source_shin", Quat::from_rotation_x(-0.7));
+    rig.step(2);
+
+    for (bone, rest_rotation) in rest_rotations {
+        let source = rotation(&rig, &format!("source_{bone}"));
+        let target = rotation(&rig, &format!("target_{bone}"));
+        assert!((rest_rotation * source).angle_between(target) < TOLERANCE);
+    }
+}
+
+#[test]
+fn root_translation_is_scaled() {
+    let mut rig = legs();
+    retarget(&mut rig, Vec::new());
+    let hips_id = rig.bone("source_hips");
+    rig.app
+        .world
+        .get_mut::<Transform>(hips_id)
+        .unwrap()
+        .translation = Vec3::new(0., 0.9, 0.5);
+    rig.step(3);
+
+    // the ratio of the summed lengths of the thigh, shin and foot bones, the hips bone has no length
+    let target_hips = rig.bone_position("target_hips");
+    let leg = |shin: f32| leg_length(0.1, shin) + 0.1;
+    let scale = leg(0.35) / leg(0.45);
+    let expected = Vec3::Y * 0.8 + Vec3::new(0., -0.1, 0.5) * scale;
+    assert!(target_hips.distance(expected) < TOLERANCE);
+}
+
+#[test]
+fn contacts_stay_at_source_positions() {
+    let mut rig = legs();
+    retarget(&mut rig, foot_contact());
+    set_rotation(&mut rig, "source_thigh", Quat::from_rotation_x(0.3));
+    set_rotation(&mut rig, "source_shin", Quat::from_rotation_x(-0.5));
+    rig.step(4);
+
+    let source_foot = rig.bone_position("source_foot");
+    assert!(rig.bone_position("target_foot").distance(source_foot) < TOLERANCE);
+}
+
+#[test]
+fn contact_goals_are_despawned_with_the_retarget() {
+    let mut rig = legs();
+    retarget(&mut rig, foot_contact());
+    rig.step(2);
+    let goal_id = contact_goal(&rig).unwrap();
+
+    let target = rig.bone("target_hips");
+    rig.app.world.entity_mut(target).remove::<Retarget>();
+    rig.step(1);
+    assert!(rig.app.world.get_entity(goal_id).is_none());
+}
+
+#[test]
+fn contact_goals_are_despawned_once_they_dont_resolve() {
+    let mut rig = legs();
+    retarget(&mut rig, foot_contact());
+    rig.step(2);
+    let goal_id = contact_goal(&rig).unwrap();
+
+    // the contact bone isn't mapped anymore
+    let target = rig.bone("target_hips");
+    let mut target_retarget = rig.app.world.get_mut::<Retarget>(target).unwrap();
+    target_retarget.bone_map.remove("target_foot");
+    rig.step(1);
+    assert!(rig.app.world.get_entity(goal_id).is_none());
+    assert_eq!(contact_goal(&rig), None);
+}
+
+#[test]
+fn goals_of_removed_contacts_are_despawned() {
+    let mut rig = legs();
+    retarget(&mut rig, foot_contact());
+    rig.step(2);
+    let goal_id = contact_goal(&rig).unwrap();
+
+    let target = rig.bone("target_hips");
+    let mut target_retarget = rig.app.world.get_mut::<Retarget>(target).unwrap();
+    target_retarget.contacts.clear();
+    rig.step(1);
+    assert!(rig.app.world.get_entity(goal_id).is_none());
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "rig_asset")]
+mod common;
+
+use bevy::{asset::AssetPlugin, prelude::*};
+use bevy_ik::{
+    BoneStiffness, IkGoal, IkGoalAnchor, IkRig, IkRigAnchor, IkRigBone, IkRigGoal, IkRigInstance,
+    IkRigPlugin,
+};
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+fn rig_asset() -> IkRig {
+    IkRig {
+        goals: vec![IkRigGoal {
+            bone: "hand".to_string(),
+            chain_length: 2,
+            position: Some(Vec3::new(2., 3., 1.)),
+            anchor: None,
+            planted: false,
+        }],
+        bones: vec![IkRigBone {
+            bone: "upper_arm".to_string(),
+            stiffness: Some(0.5),
+            mass: None,
+        }],
+    }
+}
+
+/// an arm with a rig handle on its armature entity, but without bones yet. The stiff upper arm converges
+/// over a few frames.
+fn rigged(asset: IkRig) -> TestRig {
+    let mut rig = TestRig::new();
+    rig.app
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(IkRigPlugin);
+    let handle = rig.app.world.resource_mut::<Assets<IkRig>>().add(asset);
+    let armature = rig.armature;
+    rig.app.world.entity_mut(armature).insert(handle);
+    rig
+}
+
+fn spawn_arm(rig: &mut TestRig) {
+    rig.spawn_chain(
+        None,
+        &[
+            ("upper_arm", Vec3::ZERO),
+            ("lower_arm", Vec3::Y * 3.),
+            ("hand", Vec3::Y * 2.),
+        ],
+    );
+}
+
+fn instance(rig: &TestRig) -> Option<&IkRigInstance> {
+    rig.app.world.get::<IkRigInstance>(rig.armature)
+}
+
+#[test]
+fn rig_is_applied_to_bones() {
+    let mut rig = rigged(rig_asset());
+    spawn_arm(&mut rig);
+    rig.step(10);
+
+    let goal_id = instance(&rig).unwrap().goals[0];
+    let goal = rig.app.world.get::<IkGoal>(goal_id).unwrap();
+    assert_eq!(goal.target_bone, rig.bone("hand"));
+    assert_eq!(goal.chain_length, 2);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+    let stiffness = rig.app.world.get::<BoneStiffness>(rig.bone("upper_arm"));
+    assert_eq!(stiffness.map(|stiffness| stiffness.0), Some(0.5));
+}
+
+#[test]
+fn rig_waits_for_late_bones() {
+    let mut rig = rigged(rig_asset());
+    // only the upper arm exists, e.g. while the scene is still being spawned
+    rig.spawn_chain(None, &[("upper_arm", Vec3::ZERO)]);
+    rig.step(2);
+    assert!(instance(&rig).is_none());
+
+    rig.spawn_chain(
+        Some("upper_arm"),
+        &[("lower_arm", Vec3::Y * 3.), ("hand", Vec3::Y * 2.)],
+    );
+    rig.step(10);
+    let goal_id = instance(&rig).unwrap().goals[0];
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn anchored_goal_follows_bone() {
+    let mut asset = rig_asset();
+    asset.goals[0].position = None;
+    asset.goals[0].anchor = Some(IkRigAnchor {
+        bone: "socket".to_string(),
+        offset: Vec3::X * 0.5,
+    });
+    let mut rig = rigged(asset);
+    spawn_arm(&mut rig);
+    // a separate armature holding the socket the hand follows
+    rig.spawn_chain(None, &[("socket", Vec3::new(1., 3., 0.))]);
+    rig.step(10);
+
+    let goal_id = instance(&rig).unwrap().goals[0];
+    let anchor = rig.app.world.get::<IkGoalAnchor>(goal_id).unwrap();
+    assert_eq!(anchor.entity, rig.bone("socket"));
+    let expected = Vec3::new(1.5, 3., 0.);
+    assert!(rig.bone_position("hand").distance(expected) < TOLERANCE);
+}
+
+#[test]
+fn ambiguous_bone_names_are_not_applied() {
+    let mut rig = rigged(rig_asset());
+    spawn_arm(&mut rig);
+    // a separate armature with another hand
+    rig.spawn_chain(None, &[("hand", Vec3::new(1., 3., 0.))]);
+    rig.step(10);
+    assert!(instance(&rig).is_none());
+
+    // names of multiple bones of the same armature are ambiguous as well
+    let mut rig = rigged(rig_asset());
+    spawn_arm(&mut rig);
+    rig.spawn_chain(Some("lower_arm"), &[("hand", Vec3::X)]);
+    rig.step(10);
+    assert!(instance(&rig).is_none());
+}
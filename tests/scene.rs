@@ -0,0 +1,193 @@
+mod common;
+
+use bevy::{ecs::entity::EntityMap, prelude::*, scene::serde::SceneDeserializer};
+use bevy_ik::{
+    FootPlacement, FootPlacementPlugin, Gait, GaitPlugin, Grip, GripFinger, GripPlugin, GripShape,
+    IkGoal, IkGoalAnchor, IkSmoothing, Leg, Pelvis, RestPose, Retarget, RetargetContact,
+    RetargetPlugin, RootMotion, SplineIk,
+};
+use common::TestRig;
+use serde::de::DeserializeSeed;
+
+const TOLERANCE: f32 = 0.01;
+
+fn rig() -> TestRig {
+    let mut rig = TestRig::new();
+    rig.app.add_plugin(HierarchyPlugin);
+    rig
+}
+
+fn plugin_rig() -> TestRig {
+    let mut rig = rig();
+    rig.app
+        .add_plugin(FootPlacementPlugin)
+        .add_plugin(GaitPlugin)
+        .add_plugin(GripPlugin)
+        .add_plugin(RetargetPlugin);
+    rig
+}
+
+/// Saves the world of `rig` as a scene in RON and loads it into the `loaded` rig.
+fn round_trip(rig: &TestRig, mut loaded: TestRig) -> (TestRig, EntityMap) {
+    let registry = rig.app.world.resource::<AppTypeRegistry>().clone();
+    let ron = DynamicScene::from_world(&rig.app.world, &registry)
+        .serialize_ron(&registry)
+        .unwrap();
+    let mut deserializer = ron::de::Deserializer::from_str(&ron).unwrap();
+    let scene = SceneDeserializer {
+        type_registry: &registry.read(),
+    }
+    .deserialize(&mut deserializer)
+    .unwrap();
+
+    let mut entity_map = EntityMap::default();
+    scene
+        .write_to_world(&mut loaded.app.world, &mut entity_map)
+        .unwrap();
+    for (name, bone_id) in rig.bones.iter() {
+        loaded
+            .bones
+            .insert(name.clone(), entity_map.get(*bone_id).unwrap());
+    }
+    for (bone_id, par_id) in rig.bone_parents.iter() {
+        loaded.bone_parents.insert(
+            entity_map.get(*bone_id).unwrap(),
+            entity_map.get(*par_id).unwrap(),
+        );
+    }
+    (loaded, entity_map)
+}
+
+#[test]
+fn goals_survive_scene_round_trip() {
+    let mut rig = rig();
+    rig.spawn_chain(
+        None,
+        &[
+            ("upper_arm", Vec3::ZERO),
+            ("lower_arm", Vec3::Y * 3.0),
+            ("hand", Vec3::Y * 2.0),
+        ],
+    );
+    let socket = rig
+        .app
+        .world
+        .spawn(TransformBundle::from_transform(Transform::from_xyz(
+            2.0, 3.0, 0.0,
+        )))
+        .id();
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::ZERO);
+    rig.app.world.entity_mut(goal_id).insert(IkGoalAnchor {
+        entity: socket,
+        offset: Transform::from_xyz(0.0, 0.5, 0.0),
+    });
+    rig.step(3);
+    let lengths = rig.bone_lengths();
+
+    let (mut loaded, entity_map) = round_trip(&rig, self::rig());
+    let loaded_goal = entity_map.get(goal_id).unwrap();
+    let goal = loaded.app.world.get::<IkGoal>(loaded_goal).unwrap();
+    assert_eq!(goal.target_bone, loaded.bone("hand"));
+    let anchor = loaded.app.world.get::<IkGoalAnchor>(loaded_goal).unwrap();
+    assert_eq!(anchor.entity, entity_map.get(socket).unwrap());
+    let root_id = loaded.bone("upper_arm");
+    let rest_pose = loaded.app.world.get::<RestPose>(root_id).unwrap();
+    assert!(rest_pose.transforms.contains_key(&loaded.bone("hand")));
+
+    // the loaded rig keeps solving towards the anchor
+    *loaded
+        .app
+        .world
+        .get_mut::<Transform>(anchor.entity)
+        .unwrap() = Transform::from_xyz(-2.0, 2.0, 1.0);
+    loaded.step(2);
+    let expected = Vec3::new(-2.0, 2.5, 1.0);
+    assert!(loaded.bone_position("hand").distance(expected) < TOLERANCE);
+    loaded.assert_bone_lengths(&lengths, TOLERANCE);
+}
+
+#[test]
+fn plugin_components_survive_scene_round_trip() {
+    let mut rig = plugin_rig();
+    rig.spawn_chain(None, &[("hips", Vec3::Y)])
+        .spawn_leg(Some("hips"), "", 0., 0.45);
+    let hips = rig.bone("hips");
+    rig.app.world.entity_mut(hips).insert((
+        IkSmoothing::default(),
+        RootMotion::default(),
+        Pelvis::default(),
+        Retarget {
+            source: hips,
+            bone_map: [("foot".to_string(), "foot".to_string())].into(),
+            contacts: vec![RetargetContact {
+                bone: "foot".to_string(),
+                chain_length: 2,
+                goal: None,
+            }],
+        },
+    ));
+    let body = rig.armature;
+    rig.app.world.entity_mut(body).insert((
+        Gait::default(),
+        Grip {
+            shape: GripShape::Contacts(vec![Vec3::X]),
+        },
+    ));
+    let goal_id = rig.spawn_goal("foot", 2, Vec3::ZERO);
+    rig.app.world.entity_mut(goal_id).insert((
+        FootPlacement::default(),
+        Leg {
+            body,
+            rest_offset: Vec3::X,
+            group: 0,
+            planted: None,
+            step: None,
+        },
+        GripFinger {
+            grip: body,
+            contact: 0,
+            pad: 0.0,
+        },
+    ));
+    let tip_bone = rig.bone("foot");
+    let spline_id = rig
+        .app
+        .world
+        .spawn(SplineIk {
+            tip_bone,
+            chain_length: 2,
+            controls: vec![goal_id],
+            curve: default(),
+            twist_axis: Vec3::Y,
+            last_rotations: default(),
+        })
+        .id();
+    rig.step(3);
+
+    let (loaded, entity_map) = round_trip(&rig, plugin_rig());
+    let world = &loaded.app.world;
+    let loaded_hips = loaded.bone("hips");
+    let loaded_goal = entity_map.get(goal_id).unwrap();
+    let loaded_body = entity_map.get(body).unwrap();
+    let retarget = world.get::<Retarget>(loaded_hips).unwrap();
+    assert_eq!(retarget.source, loaded_hips);
+    assert!(retarget.contacts[0].goal.is_some());
+    assert!(world.get::<RootMotion>(loaded_hips).is_some());
+    let smoothing = world.get::<IkSmoothing>(loaded_hips).unwrap();
+    assert!(smoothing
+        .last_rotations
+        .keys()
+        .all(|bone_id| loaded.bones.values().any(|id| id == bone_id)));
+    assert_eq!(world.get::<Leg>(loaded_goal).unwrap().body, loaded_body);
+    assert_eq!(
+        world.get::<GripFinger>(loaded_goal).unwrap().grip,
+        loaded_body
+    );
+    assert!(world.get::<FootPlacement>(loaded_goal).is_some());
+    assert!(world.get::<Gait>(loaded_body).is_some());
+    let spline = world
+        .get::<SplineIk>(entity_map.get(spline_id).unwrap())
+        .unwrap();
+    assert_eq!(spline.tip_bone, loaded.bone("foot"));
+    assert_eq!(spline.controls, vec![loaded_goal]);
+}
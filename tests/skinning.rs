@@ -0,0 +1,39 @@
+#![cfg(feature = "skinning")]
+
+use bevy::{prelude::*, render::mesh::skinning::SkinnedMesh};
+use bevy_ik::{Bone, SkinnedMeshBonesPlugin};
+
+/// a skinned mesh with two joints and a helper entity which is not a joint
+fn skinned_mesh(plugin: SkinnedMeshBonesPlugin) -> (App, [Entity; 3]) {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).add_plugin(plugin);
+    let entities =
+        ["bone_root", "bone_arm", "ik_helper"].map(|name| app.world.spawn(Name::new(name)).id());
+    app.world.spawn(SkinnedMesh {
+        inverse_bindposes: Handle::default(),
+        joints: entities[..2].to_vec(),
+    });
+    app.update();
+    (app, entities)
+}
+
+fn bone_name(app: &App, entity: Entity) -> Option<&str> {
+    app.world.get::<Bone>(entity).map(|bone| bone.name.as_str())
+}
+
+#[test]
+fn skin_joints_are_tagged() {
+    let (app, [root, arm, helper]) = skinned_mesh(SkinnedMeshBonesPlugin::default());
+    assert_eq!(bone_name(&app, root), Some("bone_root"));
+    assert_eq!(bone_name(&app, arm), Some("bone_arm"));
+    assert_eq!(bone_name(&app, helper), None);
+}
+
+#[test]
+fn name_filter_skips_joints() {
+    let (app, [root, arm, _]) = skinned_mesh(SkinnedMeshBonesPlugin {
+        name_filter: Some("arm".to_string()),
+    });
+    assert_eq!(bone_name(&app, root), None);
+    assert_eq!(bone_name(&app, arm), Some("bone_arm"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::IkSmoothing;
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+/// an arm with a smoothed two bone chain, reaching for a goal in front of the hand
+fn arm() -> (TestRig, Entity) {
+    let mut rig = TestRig::new();
+    rig.spawn_chain(
+        None,
+        &[
+            ("shoulder", Vec3::ZERO),
+            ("upper_arm", Vec3::X),
+            ("forearm", Vec3::new(1., 0.2, 0.)),
+            ("hand", Vec3::new(1., -0.2, 0.)),
+        ],
+    );
+    let shoulder = rig.bone("shoulder");
+    rig.app.world.entity_mut(shoulder).insert(IkSmoothing {
+        half_life: 0.1,
+        ..default()
+    });
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(3., 0., 0.));
+    rig.step_seconds(3, 0.01);
+    (rig, goal_id)
+}
+
+fn rotation(rig: &TestRig, bone: &str) -> Quat {
+    rig.app
+        .world
+        .get::<Transform>(rig.bone(bone))
+        .unwrap()
+        .rotation
+}
+
+#[test]
+fn smoothing_is_frame_rate_independent() {
+    let mut poses = Vec::new();
+    for (frames, delta_seconds) in [(6, 1. / 30.), (24, 1. / 120.)] {
+        let (mut rig, goal_id) = arm();
+        rig.move_goal(goal_id, Vec3::new(1.5, 1.5, 0.));
+        rig.step_seconds(frames, delta_seconds);
+
+        // half way there after one half life, but not at the goal yet
+        assert!(rig.goal_distance(goal_id) > 0.1);
+        poses.push((rotation(&rig, "upper_arm"), rotation(&rig, "forearm")));
+    }
+
+    let ((upper_30, fore_30), (upper_120, fore_120)) = (poses[0], poses[1]);
+    assert!(upper_30.angle_between(upper_120) < TOLERANCE);
+    assert!(fore_30.angle_between(fore_120) < TOLERANCE);
+}
+
+#[test]
+fn smoothing_reaches_the_goal() {
+    let (mut rig, goal_id) = arm();
+    rig.move_goal(goal_id, Vec3::new(1.5, 1.5, 0.));
+    rig.step_seconds(100, 1. / 60.);
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
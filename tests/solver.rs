@@ -0,0 +1,343 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{ArmatureGraph, BoneStiffness, IkData, IkGoal, IkGoalAnchor, IkGoalLink, RootMotion};
+use common::TestRig;
+use std::f32::consts::FRAC_PI_2;
+
+const TOLERANCE: f32 = 0.01;
+
+fn arm() -> TestRig {
+    let mut rig = TestRig::new();
+    rig.spawn_chain(
+        None,
+        &[
+            ("upper_arm", Vec3::ZERO),
+            ("lower_arm", Vec3::Y * 3.0),
+            ("hand", Vec3::Y * 2.0),
+            ("fingers", Vec3::Y),
+        ],
+    );
+    rig
+}
+
+#[test]
+fn reachable_goal_is_reached() {
+    let mut rig = arm();
+    rig.step(1);
+    let lengths = rig.bone_lengths();
+
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(2.0, 3.0, 1.0));
+    rig.step(3);
+
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+    rig.assert_bone_lengths(&lengths, TOLERANCE);
+}
+
+#[test]
+fn moving_goal_is_followed() {
+    let mut rig = arm();
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(2.0, 3.0, 1.0));
+    rig.step(3);
+
+    for position in [Vec3::new(-1.0, 2.0, 2.0), Vec3::new(0.0, 1.0, -4.0)] {
+        rig.move_goal(goal_id, position);
+        rig.step(2);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+}
+
+#[test]
+fn anchored_goal_follows_moving_entity() {
+    let mut rig = arm();
+    // a rotated socket, the offset is applied in its local space
+    let socket = rig
+        .app
+        .world
+        .spawn(TransformBundle::from_transform(
+            Transform::from_xyz(2.0, 3.0, 0.0).with_rotation(Quat::from_rotation_z(FRAC_PI_2)),
+        ))
+        .id();
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::ZERO);
+    rig.app.world.entity_mut(goal_id).insert(IkGoalAnchor {
+        entity: socket,
+        offset: Transform::from_xyz(0.5, 0.0, 0.0),
+    });
+    rig.step(2);
+    assert!(rig.bone_position("hand").distance(Vec3::new(2.0, 3.5, 0.0)) < TOLERANCE);
+
+    // the goal is resolved after propagation, so the hand follows the socket in the same frame
+    for position in [Vec3::new(-1.0, 2.0, 2.0), Vec3::new(0.0, 1.0, -3.0)] {
+        *rig.app.world.get_mut::<Transform>(socket).unwrap() =
+            Transform::from_translation(position).with_rotation(Quat::from_rotation_z(FRAC_PI_2));
+        rig.step(1);
+        let expected = position + Vec3::Y * 0.5;
+        assert!(rig.bone_position("hand").distance(expected) < TOLERANCE);
+    }
+}
+
+#[test]
+fn unreachable_goal_stretches_chain() {
+    let mut rig = arm();
+    rig.step(1);
+    let lengths = rig.bone_lengths();
+
+    rig.spawn_goal("hand", 2, Vec3::new(10.0, 0.0, 0.0));
+    rig.step(3);
+
+    // the chain points straight at the goal without being stretched
+    assert!(rig.bone_position("hand").distance(Vec3::X * 5.0) < TOLERANCE);
+    rig.assert_bone_lengths(&lengths, TOLERANCE);
+}
+
+#[test]
+fn chain_length_limits_solved_bones() {
+    let mut rig = arm();
+    rig.step(1);
+    let upper_arm = rig.bone_position("upper_arm");
+    let lower_arm = rig.bone_position("lower_arm");
+
+    let goal_id = rig.spawn_goal("fingers", 1, Vec3::new(0.6, 5.8, 0.0));
+    rig.step(3);
+
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+    assert!(rig.bone_position("upper_arm").distance(upper_arm) < TOLERANCE);
+    assert!(rig.bone_position("lower_arm").distance(lower_arm) < TOLERANCE);
+}
+
+#[test]
+fn fork_with_two_goals() {
+    let mut rig = TestRig::new();
+    // both arms start at the same joint at the end of the spine
+    rig.spawn_chain(None, &[("root", Vec3::ZERO), ("spine", Vec3::Y)])
+        .spawn_chain(
+            Some("spine"),
+            &[
+                ("left_arm", Vec3::Y),
+                ("left_hand", Vec3::new(-1.0, 1.0, 0.0)),
+            ],
+        )
+        .spawn_chain(
+            Some("spine"),
+            &[
+                ("right_arm", Vec3::Y),
+                ("right_hand", Vec3::new(1.0, 1.0, 0.0)),
+            ],
+        );
+    rig.step(1);
+    let lengths = rig.bone_lengths();
+
+    let left_id = rig.spawn_goal("left_hand", 3, Vec3::new(-1.0, 2.2, 0.5));
+    let right_id = rig.spawn_goal("right_hand", 3, Vec3::new(1.0, 2.2, 0.5));
+    rig.step(3);
+
+    rig.assert_goal_reached(left_id, TOLERANCE);
+    rig.assert_goal_reached(right_id, TOLERANCE);
+    rig.assert_bone_lengths(&lengths, TOLERANCE);
+}
+
+#[test]
+fn stiff_bone_rotates_less() {
+    let goal_pos = Vec3::new(2.0, 3.0, 1.0);
+    let mut directions = Vec::new();
+    for stiffness in [None, Some(0.5)] {
+        let mut rig = arm();
+        if let Some(stiffness) = stiffness {
+            let upper_arm = rig.bone("upper_arm");
+            rig.app
+                .world
+                .entity_mut(upper_arm)
+                .insert(BoneStiffness(stiffness));
+        }
+        let goal_id = rig.spawn_goal("hand", 2, goal_pos);
+        rig.step(1);
+        directions.push(rig.bone_position("lower_arm").normalize());
+
+        // the stiffness is handed to the solver for the joint the bone ends at
+        let upper_arm = rig.bone("upper_arm");
+        let joint = rig.app.world.resource::<ArmatureGraph>().pole_joint[&upper_arm];
+        let data = rig.app.world.resource::<IkData>();
+        assert_eq!(data.skeleton.stiffness.get(&joint).copied(), stiffness);
+
+        // stiff bones are only rotated by a part of the way in each frame, they converge over a few frames
+        rig.step(10);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+
+    // in the first frame, the stiff upper arm stays closer to its direction before solving
+    let [free, stiff] = [directions[0], directions[1]];
+    assert!(stiff.angle_between(Vec3::Y) < free.angle_between(Vec3::Y) - TOLERANCE);
+}
+
+#[test]
+fn separate_armatures_are_solved_independently() {
+    let mut rig = TestRig::new();
+    rig.spawn_chain(
+        None,
+        &[
+            ("left_shoulder", Vec3::new(-2.0, 0.0, 0.0)),
+            ("left_elbow", Vec3::Y),
+            ("left_hand", Vec3::Y),
+        ],
+    )
+    .spawn_chain(
+        None,
+        &[
+            ("right_shoulder", Vec3::new(2.0, 0.0, 0.0)),
+            ("right_elbow", Vec3::Y),
+            ("right_hand", Vec3::Y),
+        ],
+    );
+    rig.step(1);
+    let lengths = rig.bone_lengths();
+
+    let left_id = rig.spawn_goal("left_hand", 2, Vec3::new(-1.0, 1.0, 0.0));
+    let right_id = rig.spawn_goal("right_hand", 2, Vec3::new(2.0, 1.0, 1.0));
+    rig.step(3);
+
+    rig.assert_goal_reached(left_id, TOLERANCE);
+    rig.assert_goal_reached(right_id, TOLERANCE);
+    rig.assert_bone_lengths(&lengths, TOLERANCE);
+    assert!(rig.bone_position("left_shoulder").distance(Vec3::X * -2.0) < TOLERANCE);
+    assert!(rig.bone_position("right_shoulder").distance(Vec3::X * 2.0) < TOLERANCE);
+}
+
+#[test]
+fn chain_below_rotated_and_scaled_parent() {
+    let mut rig = arm();
+    // the root joint ends up at (1, 0, 0)
+    rig.set_armature_transform(
+        Transform::from_xyz(1.0, 0.0, 0.0)
+            .with_rotation(Quat::from_rotation_z(FRAC_PI_2))
+            .with_scale(Vec3::splat(0.5)),
+    );
+    rig.step(1);
+    let lengths = rig.bone_lengths();
+
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(1.0, 1.0, 1.0));
+    rig.step(3);
+
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+    rig.assert_bone_lengths(&lengths, TOLERANCE);
+}
+
+#[test]
+fn chain_below_non_uniformly_scaled_parent() {
+    let mut rig = arm();
+    rig.set_armature_transform(
+        Transform::from_rotation(Quat::from_rotation_y(0.3)).with_scale(Vec3::new(0.5, 1.0, 2.0)),
+    );
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(1.0, 2.0, 1.0));
+    // the world length of the bones depends on their direction, so the solution converges over a few frames
+    rig.step(30);
+
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn root_motion_moves_root_towards_goal() {
+    // the armature is rotated and scaled as well, so the offset has to be moved into the space of the parent
+    let armature_tfs = [
+        Transform::IDENTITY,
+        Transform::from_xyz(0.5, 0.0, -1.0)
+            .with_rotation(Quat::from_rotation_z(0.4) * Quat::from_rotation_x(0.3))
+            .with_scale(Vec3::splat(1.5)),
+    ];
+    for armature_tf in armature_tfs {
+        let mut rig = arm();
+        rig.set_armature_transform(armature_tf);
+        let root_id = rig.bone("upper_arm");
+        rig.app.world.entity_mut(root_id).insert(RootMotion {
+            max_offset: 1.0,
+            ..default()
+        });
+        rig.step(1);
+        let lengths = rig.bone_lengths();
+
+        // just out of reach of the arm
+        let goal = armature_tf.transform_point(Vec3::new(3.0, 4.5, 0.0));
+        let goal_id = rig.spawn_goal("hand", 2, goal);
+        rig.step(3);
+
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+        rig.assert_bone_lengths(&lengths, TOLERANCE);
+
+        let root_offset = |rig: &TestRig| rig.bone_position("upper_arm") - armature_tf.translation;
+        let offset = rig.app.world.get::<RootMotion>(root_id).unwrap().offset;
+        assert!(offset.length() > 0.3 && offset.length() < 1.0);
+        assert!(root_offset(&rig).distance(offset) < TOLERANCE);
+
+        // the root is moved back each frame, so the offsets don't accumulate, and are reported each frame
+        rig.step(3);
+        let offset = rig.app.world.get::<RootMotion>(root_id).unwrap().offset;
+        assert!(offset.length() > 0.3 && offset.length() < 1.0);
+        assert!(root_offset(&rig).distance(offset) < TOLERANCE);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+    }
+}
+
+#[test]
+fn linked_goals_keep_their_offset() {
+    let mut rig = TestRig::new();
+    rig.spawn_chain(None, &[("root", Vec3::ZERO), ("spine", Vec3::Y)])
+        .spawn_chain(
+            Some("spine"),
+            &[
+                ("left_arm", Vec3::Y),
+                ("left_hand", Vec3::new(-1.0, 1.0, 0.0)),
+            ],
+        )
+        .spawn_chain(
+            Some("spine"),
+            &[
+                ("right_arm", Vec3::Y),
+                ("right_hand", Vec3::new(1.0, 1.0, 0.0)),
+            ],
+        );
+    rig.step(1);
+
+    // the left goal is out of reach on its own
+    let left_goal = Vec3::new(-1.6, 3.2, 0.0);
+    let right_goal = Vec3::new(0.6, 3.2, 0.0);
+    let right_id = rig.spawn_goal("right_hand", 3, right_goal);
+    let left_id = rig.spawn_goal("left_hand", 3, left_goal);
+    rig.app.world.entity_mut(right_id).insert(IkGoalLink {
+        other: left_id,
+        error: Vec3::ZERO,
+    });
+    rig.step(3);
+
+    let hands = rig.bone_position("right_hand") - rig.bone_position("left_hand");
+    assert!(hands.distance(right_goal - left_goal) < TOLERANCE);
+    let error = rig.app.world.get::<IkGoalLink>(right_id).unwrap().error;
+    assert!(error.length() > 0.1);
+    assert!((rig.goal_distance(right_id) - error.length()).abs() < TOLERANCE);
+    assert!((rig.goal_distance(left_id) - error.length()).abs() < TOLERANCE);
+}
+
+#[test]
+fn linked_goal_without_global_transform_is_skipped() {
+    let mut rig = arm();
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(2.0, 3.0, 1.0));
+    // a goal inserted without the bundle has no position to solve for
+    let target_bone = rig.bone("fingers");
+    let linked_id = rig
+        .app
+        .world
+        .spawn((
+            IkGoal {
+                target_bone,
+                chain_length: 1,
+            },
+            IkGoalLink {
+                other: goal_id,
+                error: Vec3::ZERO,
+            },
+        ))
+        .id();
+    rig.step(3);
+
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+    let link = rig.app.world.get::<IkGoalLink>(linked_id).unwrap();
+    assert_eq!(link.error, Vec3::ZERO);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{InverseKinematicsPlugin, SplineCurve, SplineIk, TwistSource};
+use common::TestRig;
+use std::f32::consts::FRAC_PI_2;
+
+const TOLERANCE: f32 = 0.01;
+
+/// a vertical tail of six bones, 0.5 apart, with a leaf bone at its end
+fn tail() -> TestRig {
+    tail_with_lengths([0.5; 6])
+}
+
+fn tail_with_lengths(lengths: [f32; 6]) -> TestRig {
+    tail_with(lengths, TwistSource::Rest)
+}
+
+fn tail_with(lengths: [f32; 6], twist_source: TwistSource) -> TestRig {
+    let mut rig = TestRig::with_plugin(InverseKinematicsPlugin {
+        twist_source,
+        ..default()
+    });
+    rig.spawn_chain(
+        None,
+        &[
+            ("tail0", Vec3::ZERO),
+            ("tail1", Vec3::Y * lengths[0]),
+            ("tail2", Vec3::Y * lengths[1]),
+            ("tail3", Vec3::Y * lengths[2]),
+            ("tail4", Vec3::Y * lengths[3]),
+            ("tail5", Vec3::Y * lengths[4]),
+            ("tip", Vec3::Y * lengths[5]),
+        ],
+    );
+    rig.step(1);
+    rig
+}
+
+fn global_rotation(rig: &TestRig, bone: &str) -> Quat {
+    rig.app
+        .world
+        .get::<GlobalTransform>(rig.bone(bone))
+        .unwrap()
+        .to_scale_rotation_translation()
+        .1
+}
+
+fn spawn_spline(rig: &mut TestRig, controls: &[Transform], curve: SplineCurve) {
+    let controls = controls
+        .iter()
+        .map(|control| {
+            rig.app
+                .world
+                .spawn(TransformBundle::from_transform(*control))
+                .id()
+        })
+        .collect();
+    let tip_bone = rig.bone("tail5");
+    rig.app.world.spawn(SplineIk {
+        tip_bone,
+        chain_length: 6,
+        controls,
+        curve,
+        twist_axis: Vec3::Y,
+        last_rotations: default(),
+    });
+}
+
+#[test]
+fn tail_follows_curve() {
+    for curve in [SplineCurve::CatmullRom, SplineCurve::Bezier] {
+        let mut rig = tail();
+        let lengths = rig.bone_lengths();
+        let controls = [Vec3::new(1., 1.5, 0.), Vec3::new(2., 0.5, 0.)];
+        spawn_spline(&mut rig, &controls.map(Transform::from_translation), curve);
+        rig.step(2);
+
+        rig.assert_bone_lengths(&lengths, TOLERANCE);
+        let polyline = curve.sample(&[Vec3::ZERO, controls[0], controls[1]]);
+        for bone in ["tail1", "tail2", "tail3", "tail4", "tail5"] {
+            let pos = rig.bone_position(bone);
+            let dist = polyline
+                .iter()
+                .map(|point| point.distance(pos))
+                .fold(f32::MAX, f32::min);
+            assert!(dist < 0.1, "{bone} is {dist} away from the {curve:?} curve");
+        }
+    }
+}
+
+#[test]
+fn twist_is_distributed_along_tail() {
+    let mut rig = tail();
+    // a straight curve, twisted by a quarter turn at its end
+    let control = Transform::from_xyz(0., 4., 0.).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+    spawn_spline(&mut rig, &[control], SplineCurve::CatmullRom);
+    rig.step(2);
+
+    let half_twist = Quat::from_rotation_y(FRAC_PI_2 / 2.);
+    assert!(global_rotation(&rig, "tail2").angle_between(half_twist) < TOLERANCE);
+    let full_twist = Quat::from_rotation_y(FRAC_PI_2);
+    assert!(global_rotation(&rig, "tail5").angle_between(full_twist) < TOLERANCE);
+    assert!(rig.bone_position("tip").distance(Vec3::Y * 3.) < TOLERANCE);
+}
+
+#[test]
+fn twist_follows_bone_lengths() {
+    // the last two bones are as long as the first four
+    let mut rig = tail_with_lengths([0.5, 0.5, 0.5, 0.5, 1., 1.]);
+    let control = Transform::from_xyz(0., 5., 0.).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+    spawn_spline(&mut rig, &[control], SplineCurve::CatmullRom);
+    rig.step(2);
+
+    // half of the length, half of the twist
+    let half_twist = Quat::from_rotation_y(FRAC_PI_2 / 2.);
+    assert!(global_rotation(&rig, "tail3").angle_between(half_twist) < TOLERANCE);
+    let full_twist = Quat::from_rotation_y(FRAC_PI_2);
+    assert!(global_rotation(&rig, "tail5").angle_between(full_twist) < TOLERANCE);
+    assert!(rig.bone_position("tip").distance(Vec3::Y * 4.) < TOLERANCE);
+}
+
+#[test]
+fn animated_twist_does_not_add_up() {
+    let mut rig = tail_with([0.5; 6], TwistSource::Animated);
+    let control = Transform::from_xyz(0., 4., 0.).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+    spawn_spline(&mut rig, &[control], SplineCurve::CatmullRom);
+
+    // without an animation, the bones keep the rotations of the last frame, which already contain the twist
+    let full_twist = Quat::from_rotation_y(FRAC_PI_2);
+    for _ in 0..5 {
+        rig.step(1);
+        assert!(global_rotation(&rig, "tail5").angle_between(full_twist) < TOLERANCE);
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{BoneRef, IkGoal, IkGoalTarget, IkGoalTargetBundle};
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+fn arm(rig: &mut TestRig, root: &str) {
+    rig.spawn_chain(
+        None,
+        &[
+            (root, Vec3::ZERO),
+            ("upper_arm", Vec3::X),
+            ("forearm", Vec3::X),
+            ("hand", Vec3::X),
+        ],
+    );
+}
+
+fn spawn_target(rig: &mut TestRig, bone: BoneRef, armature: Option<Entity>) -> Entity {
+    let position = Vec3::new(1.5, 1.5, 0.);
+    rig.app
+        .world
+        .spawn(IkGoalTargetBundle {
+            target: IkGoalTarget {
+                bone,
+                chain_length: 2,
+                armature,
+            },
+            transform: Transform::from_translation(position),
+            global_transform: GlobalTransform::from_translation(position),
+        })
+        .id()
+}
+
+fn target_bone(rig: &TestRig, goal_id: Entity) -> Option<Entity> {
+    rig.app
+        .world
+        .get::<IkGoal>(goal_id)
+        .map(|goal| goal.target_bone)
+}
+
+/// the first child bone of `bone_id`
+fn child(rig: &TestRig, bone_id: Entity) -> Entity {
+    rig.app.world.get::<Children>(bone_id).unwrap()[0]
+}
+
+#[test]
+fn targets_resolve_by_name_and_path() {
+    let mut rig = TestRig::new();
+    arm(&mut rig, "shoulder");
+    let by_name = spawn_target(&mut rig, BoneRef::Name("hand".to_string()), None);
+    let by_path = spawn_target(
+        &mut rig,
+        BoneRef::Path("shoulder/upper_arm/forearm".to_string()),
+        None,
+    );
+    rig.step(3);
+
+    assert_eq!(target_bone(&rig, by_name), Some(rig.bone("hand")));
+    assert_eq!(target_bone(&rig, by_path), Some(rig.bone("forearm")));
+}
+
+#[test]
+fn targets_are_scoped_to_their_armature() {
+    let mut rig = TestRig::new();
+    arm(&mut rig, "left");
+    arm(&mut rig, "right");
+    let left_hand = child(&rig, child(&rig, child(&rig, rig.bone("left"))));
+    let right_hand = child(&rig, child(&rig, child(&rig, rig.bone("right"))));
+    let left_root = rig.bone("left");
+    let right_root = rig.bone("right");
+    let left_goal = spawn_target(&mut rig, BoneRef::Name("hand".to_string()), Some(left_root));
+    let right_goal = spawn_target(
+        &mut rig,
+        BoneRef::Name("hand".to_string()),
+        Some(right_root),
+    );
+    rig.step(3);
+
+    assert_eq!(target_bone(&rig, left_goal), Some(left_hand));
+    assert_eq!(target_bone(&rig, right_goal), Some(right_hand));
+}
+
+#[test]
+fn targets_resolve_once_the_bone_is_spawned() {
+    let mut rig = TestRig::new();
+    let goal_id = spawn_target(&mut rig, BoneRef::Name("hand".to_string()), None);
+    rig.step(2);
+    assert_eq!(target_bone(&rig, goal_id), None);
+
+    arm(&mut rig, "shoulder");
+    rig.step(5);
+    assert_eq!(target_bone(&rig, goal_id), Some(rig.bone("hand")));
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn targets_resolve_again_when_the_bone_is_respawned() {
+    let mut rig = TestRig::new();
+    arm(&mut rig, "shoulder");
+    let goal_id = spawn_target(&mut rig, BoneRef::Name("hand".to_string()), None);
+    rig.step(3);
+    let old_hand = rig.bone("hand");
+    assert_eq!(target_bone(&rig, goal_id), Some(old_hand));
+
+    // e.g. the scene with the armature is respawned
+    let shoulder = rig.bone("shoulder");
+    rig.app.world.entity_mut(shoulder).despawn_recursive();
+    rig.step(2);
+    assert_eq!(target_bone(&rig, goal_id), None);
+
+    arm(&mut rig, "shoulder");
+    rig.step(5);
+    let new_hand = rig.bone("hand");
+    assert_ne!(new_hand, old_hand);
+    assert_eq!(target_bone(&rig, goal_id), Some(new_hand));
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+}
+
+#[test]
+fn ambiguous_names_are_not_resolved() {
+    let mut rig = TestRig::new();
+    arm(&mut rig, "shoulder");
+    rig.spawn_chain(Some("shoulder"), &[("hand", Vec3::NEG_X)]);
+    let by_name = spawn_target(&mut rig, BoneRef::Name("hand".to_string()), None);
+    let by_path = spawn_target(
+        &mut rig,
+        BoneRef::Path("shoulder/upper_arm/forearm/hand".to_string()),
+        None,
+    );
+    rig.step(3);
+
+    let forearm = rig.bone("forearm");
+    assert_eq!(target_bone(&rig, by_name), None);
+    assert_eq!(target_bone(&rig, by_path), Some(child(&rig, forearm)));
+}
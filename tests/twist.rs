@@ -0,0 +1,99 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::{InverseKinematicsPlugin, TwistHelper, TwistSource};
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+/// an arm along the x axis, with a forearm twist bone next to the hand
+fn arm(twist_source: TwistSource) -> TestRig {
+    let mut rig = TestRig::with_plugin(InverseKinematicsPlugin {
+        twist_source,
+        ..default()
+    });
+    rig.spawn_chain(
+        None,
+        &[
+            ("shoulder", Vec3::ZERO),
+            ("upper_arm", Vec3::X),
+            ("forearm", Vec3::X),
+            ("hand", Vec3::X),
+        ],
+    );
+    rig.spawn_chain(Some("forearm"), &[("forearm_twist", Vec3::X)]);
+    rig.step(1);
+    rig
+}
+
+fn rotation(rig: &TestRig, bone: &str) -> Quat {
+    rig.app
+        .world
+        .get::<Transform>(rig.bone(bone))
+        .unwrap()
+        .rotation
+}
+
+fn set_rotation(rig: &mut TestRig, bone: &str, rotation: Quat) {
+    let bone_id = rig.bone(bone);
+    rig.app
+        .world
+        .get_mut::<Transform>(bone_id)
+        .unwrap()
+        .rotation = rotation;
+}
+
+/// the angle a rotation twists around the x axis, which is the direction of all bones
+fn twist(rotation: Quat) -> f32 {
+    let twist = Quat::from_xyzw(rotation.x, 0., 0., rotation.w).normalize();
+    2. * twist.x.atan2(twist.w)
+}
+
+/// Solves the arm while an animation twists the forearm, returns the solved twist of the forearm.
+fn solved_forearm_twist(twist_source: TwistSource) -> f32 {
+    let mut rig = arm(twist_source);
+    let goal_id = rig.spawn_goal("hand", 2, Vec3::new(1.5, 1.5, 0.));
+    for _ in 0..3 {
+        set_rotation(&mut rig, "forearm", Quat::from_rotation_x(0.6));
+        rig.step(1);
+    }
+    rig.assert_goal_reached(goal_id, TOLERANCE);
+    twist(rotation(&rig, "forearm"))
+}
+
+#[test]
+fn solved_bones_keep_the_rest_twist() {
+    assert!(solved_forearm_twist(TwistSource::Rest).abs() < TOLERANCE);
+}
+
+#[test]
+fn solved_bones_keep_the_animated_twist() {
+    assert!((solved_forearm_twist(TwistSource::Animated) - 0.6).abs() < TOLERANCE);
+}
+
+#[test]
+fn twist_helpers_add_their_share_to_the_animation() {
+    let mut rig = arm(TwistSource::Rest);
+    let source = rig.bone("hand");
+    let helper_id = rig.bone("forearm_twist");
+    rig.app.world.entity_mut(helper_id).insert(TwistHelper {
+        source,
+        axis: Vec3::X,
+        weight: 0.5,
+        last_rotation: None,
+    });
+
+    // the animation twists the hand and bends the twist bone
+    let animated = Quat::from_rotation_y(0.3);
+    let expected = animated * Quat::from_rotation_x(0.4);
+    for _ in 0..3 {
+        set_rotation(&mut rig, "hand", Quat::from_rotation_x(0.8));
+        set_rotation(&mut rig, "forearm_twist", animated);
+        rig.step(1);
+        assert!(rotation(&rig, "forearm_twist").angle_between(expected) < TOLERANCE);
+    }
+
+    // without the animation the twist doesn't add up over the frames
+    rig.step(3);
+    assert!(rotation(&rig, "forearm_twist").angle_between(expected) < TOLERANCE);
+}
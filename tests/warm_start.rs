@@ -0,0 +1,136 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_ik::InverseKinematicsPlugin;
+use common::TestRig;
+
+const TOLERANCE: f32 = 0.01;
+
+/// two animated poses of the arm, each frame the animation sets one of them before solving
+const POSES: [[(&str, f32); 2]; 2] = [
+    [("shoulder", 0.), ("upper_arm", 0.)],
+    [("shoulder", 0.5), ("upper_arm", -0.8)],
+];
+
+/// a three bone arm below the armature entity, with a goal that moves along with the armature
+fn arm(warm_start: bool) -> (TestRig, Entity) {
+    let mut rig = TestRig::with_plugin(InverseKinematicsPlugin {
+        warm_start,
+        ..default()
+    });
+    rig.app.add_plugin(HierarchyPlugin);
+    rig.spawn_chain(
+        None,
+        &[
+            ("shoulder", Vec3::ZERO),
+            ("upper_arm", Vec3::X),
+            ("forearm", Vec3::X),
+            ("hand", Vec3::X),
+        ],
+    );
+    let goal_id = rig.spawn_goal("hand", 3, Vec3::new(2., 1., 0.));
+    let armature = rig.armature;
+    rig.app.world.entity_mut(armature).push_children(&[goal_id]);
+    (rig, goal_id)
+}
+
+fn animate(rig: &mut TestRig, frame: usize) {
+    for (bone, angle) in POSES[frame % POSES.len()] {
+        let bone_id = rig.bone(bone);
+        rig.app
+            .world
+            .get_mut::<Transform>(bone_id)
+            .unwrap()
+            .rotation = Quat::from_rotation_z(angle);
+    }
+}
+
+/// the solved elbow position relative to the armature entity
+fn elbow(rig: &TestRig) -> Vec3 {
+    let armature_gt = rig.app.world.get::<GlobalTransform>(rig.armature).unwrap();
+    armature_gt
+        .affine()
+        .inverse()
+        .transform_point3(rig.bone_position("forearm"))
+}
+
+/// The largest distance the elbow jumps between two frames, while the animation alternates between the poses.
+fn largest_elbow_jump(rig: &mut TestRig, goal_id: Entity, frames: usize) -> f32 {
+    let mut last_elbow = None;
+    let mut largest_jump: f32 = 0.;
+    for frame in 0..frames {
+        animate(rig, frame);
+        rig.step(1);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+
+        let elbow = elbow(rig);
+        if let Some(last_elbow) = last_elbow {
+            largest_jump = largest_jump.max(elbow.distance(last_elbow));
+        }
+        last_elbow = Some(elbow);
+    }
+    largest_jump
+}
+
+#[test]
+fn cold_start_follows_the_animation() {
+    let (mut rig, goal_id) = arm(false);
+    rig.step(1);
+    assert!(largest_elbow_jump(&mut rig, goal_id, 6) > 0.1);
+}
+
+#[test]
+fn warm_start_keeps_the_pose_continuous() {
+    let (mut rig, goal_id) = arm(true);
+    rig.step(1);
+    assert!(largest_elbow_jump(&mut rig, goal_id, 6) < TOLERANCE);
+}
+
+#[test]
+fn warm_start_moves_along_with_the_armature() {
+    let (mut rig, goal_id) = arm(true);
+    animate(&mut rig, 1);
+    rig.step(2);
+    let start_elbow = elbow(&rig);
+
+    // the armature moves, e.g. by root motion, the last solution moves along with it
+    for frame in 0..6 {
+        rig.set_armature_transform(Transform {
+            translation: Vec3::new(0.3, 0., -0.2) * frame as f32,
+            rotation: Quat::from_rotation_y(0.2 * frame as f32),
+            ..default()
+        });
+        animate(&mut rig, frame);
+        rig.step(1);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+        assert!(elbow(&rig).distance(start_elbow) < TOLERANCE);
+    }
+}
+
+#[test]
+fn warm_start_survives_reparenting() {
+    let (mut rig, goal_id) = arm(true);
+    animate(&mut rig, 1);
+    rig.step(2);
+    let start_elbow = elbow(&rig);
+
+    // the armature is attached to a moved parent, e.g. a vehicle
+    let parent_tf = Transform::from_xyz(2., 0., 1.).with_rotation(Quat::from_rotation_y(1.));
+    let parent_id = rig
+        .app
+        .world
+        .spawn(TransformBundle::from_transform(parent_tf))
+        .id();
+    let armature = rig.armature;
+    rig.app
+        .world
+        .entity_mut(parent_id)
+        .push_children(&[armature]);
+
+    for frame in 0..4 {
+        animate(&mut rig, frame);
+        rig.step(1);
+        rig.assert_goal_reached(goal_id, TOLERANCE);
+        assert!(elbow(&rig).distance(start_elbow) < TOLERANCE);
+    }
+}